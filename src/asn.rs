@@ -0,0 +1,88 @@
+use crate::collectors::dns;
+use crate::config::AsnDatabaseConfig;
+use color_eyre::eyre::{Result, WrapErr};
+use maxminddb::Reader;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use tracing::info;
+
+static DATABASE: OnceLock<Reader<Vec<u8>>> = OnceLock::new();
+
+/// Fields of an ASN record, as written by MaxMind (GeoLite2/GeoIP2 ASN and ISP) and
+/// IPinfo (ASN and country/ASN) databases
+#[derive(Deserialize)]
+struct AsnRecord<'a> {
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<&'a str>,
+    /// IPinfo writes the number as `AS15169`
+    asn: Option<&'a str>,
+    name: Option<&'a str>,
+    as_name: Option<&'a str>,
+}
+
+/// Autonomous system an address is announced from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AsInfo {
+    pub number: u32,
+    pub organization: String,
+}
+
+impl AsInfo {
+    /// Adds the `asn` and `as_org` labels
+    pub fn label(&self, labels: &mut HashMap<&'static str, String>) {
+        labels.insert("asn", format!("AS{}", self.number));
+        labels.insert("as_org", self.organization.clone());
+    }
+}
+
+/// Opens the database once on boot, lookups are skipped when it is not configured
+pub fn open(config: &AsnDatabaseConfig) -> Result<()> {
+    let reader = Reader::open_readfile(&config.path)
+        .wrap_err_with(|| format!("Unable to open ASN database {:?}", config.path))?;
+
+    info!(
+        path = ?config.path,
+        database_type = reader.metadata.database_type,
+        "Opened ASN database"
+    );
+    let _ = DATABASE.set(reader);
+
+    Ok(())
+}
+
+/// Whether a database was opened, so callers can skip parsing addresses otherwise
+pub fn enabled() -> bool {
+    DATABASE.get().is_some()
+}
+
+pub fn lookup(ip: IpAddr) -> Option<AsInfo> {
+    let record: AsnRecord = DATABASE.get()?.lookup(ip).ok()?;
+
+    let number = record.autonomous_system_number.or_else(|| {
+        record
+            .asn
+            .and_then(|asn| asn.trim_start_matches("AS").parse().ok())
+    })?;
+    let organization = record
+        .autonomous_system_organization
+        .or(record.as_name)
+        .or(record.name)
+        .unwrap_or_default();
+
+    Some(AsInfo {
+        number,
+        organization: organization.to_string(),
+    })
+}
+
+/// Looks up an address as reported by the API, which may carry a `udp:`/`tcp:` scheme
+/// and a port. Anything that is not an address, such as a CNAME, is skipped.
+pub fn lookup_str(address: &str) -> Option<AsInfo> {
+    if !enabled() {
+        return None;
+    }
+
+    lookup(dns::server_ip(address)?)
+}
@@ -0,0 +1,395 @@
+use crate::collectors::ProbeOutcome;
+use crate::config::MetricConfig;
+use metrics::gauge;
+use serde::Deserialize;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// A value extracted from a probe result that assertions are evaluated against
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fact {
+    Number(f64),
+    Text(String),
+    List(Vec<String>),
+}
+
+/// Facts about a single probe, keyed by the field name used in assertions
+pub type Facts = HashMap<&'static str, Fact>;
+
+/// Facts every protocol provides: `latency_ms` and, when the probe failed, `error`
+pub fn probe_facts(duration: Option<f64>, error: Option<&String>) -> Facts {
+    let mut facts = Facts::new();
+
+    if let Some(duration) = duration {
+        facts.insert("latency_ms", Fact::Number(duration));
+    }
+    if let Some(error) = error {
+        facts.insert("error", Fact::Text(error.clone()));
+    }
+
+    facts
+}
+
+#[derive(Error, Debug)]
+pub enum AssertionError {
+    #[error("Assertion `{0}` is missing a field name")]
+    MissingField(String),
+    #[error("Assertion `{0}` has no valid operator, expected one of <, <=, >, >=, ==, !=, in, not in, contains, not contains")]
+    MissingOperator(String),
+    #[error("Assertion `{0}` is missing a value")]
+    MissingValue(String),
+    #[error("Assertion `{0}` compares with `{1}`, which needs a number")]
+    NotANumber(String, String),
+    #[error("Assertion `{0}` uses `in`, which needs a list like [200, 204]")]
+    NotAList(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    In,
+    NotIn,
+    Contains,
+    NotContains,
+}
+
+/// Longest operators first, so `<=` is not read as `<`
+const OPERATORS: [(&str, Operator); 10] = [
+    ("not contains", Operator::NotContains),
+    ("contains", Operator::Contains),
+    ("not in", Operator::NotIn),
+    ("in", Operator::In),
+    ("<=", Operator::Le),
+    (">=", Operator::Ge),
+    ("==", Operator::Eq),
+    ("!=", Operator::Ne),
+    ("<", Operator::Lt),
+    (">", Operator::Gt),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Number(f64),
+    Text(String),
+    List(Vec<Operand>),
+}
+
+/// A single condition on a probe result, e.g. `status_code in [200, 204]`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "String")]
+pub struct Assertion {
+    source: String,
+    field: String,
+    operator: Operator,
+    value: Operand,
+}
+
+impl TryFrom<String> for Assertion {
+    type Error = AssertionError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let trimmed = source.trim();
+
+        let field_len = trimmed
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(trimmed.len());
+        if field_len == 0 {
+            return Err(AssertionError::MissingField(source));
+        }
+        let (field, rest) = trimmed.split_at(field_len);
+        let rest = rest.trim_start();
+
+        let Some((token, operator, value)) = OPERATORS.iter().find_map(|(token, operator)| {
+            let value = rest.strip_prefix(token)?;
+            // Word operators must be followed by whitespace, `in` is not a prefix of `inf`
+            let is_word = token.ends_with(|c: char| c.is_ascii_alphabetic());
+            (!is_word || value.starts_with(char::is_whitespace))
+                .then_some((*token, *operator, value))
+        }) else {
+            return Err(AssertionError::MissingOperator(source));
+        };
+
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(AssertionError::MissingValue(source));
+        }
+        let value = Operand::parse(value);
+
+        match (operator, &value) {
+            (Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge, Operand::Number(_)) => {}
+            (Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge, _) => {
+                return Err(AssertionError::NotANumber(source, token.to_string()));
+            }
+            (Operator::In | Operator::NotIn, Operand::List(_)) => {}
+            (Operator::In | Operator::NotIn, _) => return Err(AssertionError::NotAList(source)),
+            _ => {}
+        }
+
+        Ok(Self {
+            field: field.to_string(),
+            operator,
+            value,
+            source,
+        })
+    }
+}
+
+impl Operand {
+    fn parse(value: &str) -> Self {
+        if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            return Operand::List(
+                split_list(items)
+                    .into_iter()
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(Operand::parse)
+                    .collect(),
+            );
+        }
+
+        for quote in ['"', '\''] {
+            if let Some(text) = value
+                .strip_prefix(quote)
+                .and_then(|v| v.strip_suffix(quote))
+            {
+                return Operand::Text(text.to_string());
+            }
+        }
+
+        value
+            .parse()
+            .map(Operand::Number)
+            .unwrap_or_else(|_| Operand::Text(value.to_string()))
+    }
+
+    fn matches(&self, fact: &Fact) -> bool {
+        match (self, fact) {
+            (Operand::Number(n), Fact::Number(f)) => n == f,
+            (Operand::Number(n), Fact::Text(t)) => t.parse::<f64>().is_ok_and(|t| t == *n),
+            (Operand::Text(s), Fact::Text(t)) => s == t,
+            (Operand::Text(s), Fact::Number(f)) => s.parse::<f64>().is_ok_and(|s| s == *f),
+            _ => false,
+        }
+    }
+
+    fn as_text(&self) -> String {
+        match self {
+            Operand::Number(n) => n.to_string(),
+            Operand::Text(t) => t.clone(),
+            Operand::List(_) => String::new(),
+        }
+    }
+}
+
+impl Assertion {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Fails whenever the field is missing from the probe's facts
+    pub fn evaluate(&self, facts: &Facts) -> bool {
+        let Some(fact) = facts.get(self.field.as_str()) else {
+            return false;
+        };
+
+        match (self.operator, &self.value, fact) {
+            (Operator::Lt, Operand::Number(v), Fact::Number(f)) => f < v,
+            (Operator::Le, Operand::Number(v), Fact::Number(f)) => f <= v,
+            (Operator::Gt, Operand::Number(v), Fact::Number(f)) => f > v,
+            (Operator::Ge, Operand::Number(v), Fact::Number(f)) => f >= v,
+            (Operator::Eq, value, fact) => value.matches(fact),
+            (Operator::Ne, value, fact) => !value.matches(fact),
+            (Operator::In, Operand::List(items), fact) => items.iter().any(|i| i.matches(fact)),
+            (Operator::NotIn, Operand::List(items), fact) => !items.iter().any(|i| i.matches(fact)),
+            (Operator::Contains, value, fact) => contains(fact, value),
+            (Operator::NotContains, value, fact) => !contains(fact, value),
+            _ => false,
+        }
+    }
+}
+
+/// Splits list items on commas outside of quotes, so `["a, b", c]` has two items
+fn split_list(items: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+
+    for (index, c) in items.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, ',') => {
+                parts.push(&items[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&items[start..]);
+
+    parts
+}
+
+fn contains(fact: &Fact, value: &Operand) -> bool {
+    let needle = value.as_text();
+
+    match fact {
+        Fact::List(items) => items.iter().any(|item| item.eq_ignore_ascii_case(&needle)),
+        Fact::Text(text) => text.contains(&needle),
+        Fact::Number(_) => false,
+    }
+}
+
+/// Evaluates the check's assertions against a probe and records the unified result.
+///
+/// A probe only succeeds if its collector reported success and every assertion passed.
+pub fn evaluate(
+    check: &MetricConfig,
+    facts: &Facts,
+    outcome: Option<ProbeOutcome>,
+) -> Option<ProbeOutcome> {
+    // Nothing to judge if the collector could not process the response
+    let outcome = outcome?;
+    let prefix = &check.prefix;
+    let check_id = check.check_id().to_string();
+
+    let mut passed = outcome == ProbeOutcome::Success;
+
+    for assertion in &check.assertions {
+        let assertion_passed = assertion.evaluate(facts);
+        passed &= assertion_passed;
+
+        gauge!(
+            format!("{}check_assertion_passed", prefix),
+            "check" => check_id.clone(),
+            "assertion" => assertion.source().to_string()
+        )
+        .set(assertion_passed as u8 as f64);
+    }
+
+    gauge!(format!("{}check_success", prefix), "check" => check_id).set(passed as u8 as f64);
+
+    Some(if passed {
+        ProbeOutcome::Success
+    } else {
+        ProbeOutcome::Failure
+    })
+}
+
+pub fn describe(check: &MetricConfig) {
+    let prefix = &check.prefix;
+
+    metrics::describe_gauge!(
+        format!("{}check_success", prefix),
+        "Whether the last probe succeeded and passed every assertion"
+    );
+
+    if !check.assertions.is_empty() {
+        metrics::describe_gauge!(
+            format!("{}check_assertion_passed", prefix),
+            "Whether the last probe passed the assertion"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Assertion, AssertionError> {
+        Assertion::try_from(source.to_string())
+    }
+
+    fn text(value: &str) -> Operand {
+        Operand::Text(value.to_string())
+    }
+
+    #[test]
+    fn parses_operators() {
+        let assertion = parse("latency_ms <= 250").unwrap();
+        assert_eq!(assertion.field, "latency_ms");
+        assert_eq!(assertion.operator, Operator::Le);
+        assert_eq!(assertion.value, Operand::Number(250.0));
+
+        assert_eq!(parse("status_code!=500").unwrap().operator, Operator::Ne);
+        assert_eq!(
+            parse("records not contains 1.2.3.4").unwrap().operator,
+            Operator::NotContains
+        );
+        assert_eq!(
+            parse("status_code not in [500]").unwrap().operator,
+            Operator::NotIn
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_assertions() {
+        assert!(matches!(
+            parse("<= 250"),
+            Err(AssertionError::MissingField(_))
+        ));
+        assert!(matches!(
+            parse("latency_ms ~ 250"),
+            Err(AssertionError::MissingOperator(_))
+        ));
+        assert!(matches!(
+            parse("latency_ms infinite"),
+            Err(AssertionError::MissingOperator(_))
+        ));
+        assert!(matches!(
+            parse("latency_ms <"),
+            Err(AssertionError::MissingValue(_))
+        ));
+        assert!(matches!(
+            parse("latency_ms < fast"),
+            Err(AssertionError::NotANumber(..))
+        ));
+        assert!(matches!(
+            parse("status_code in 200"),
+            Err(AssertionError::NotAList(_))
+        ));
+    }
+
+    #[test]
+    fn splits_lists_outside_quotes() {
+        let assertion = parse(r#"error in ["timed out, retrying", 'a,b', plain, 204]"#).unwrap();
+
+        assert_eq!(
+            assertion.value,
+            Operand::List(vec![
+                text("timed out, retrying"),
+                text("a,b"),
+                text("plain"),
+                Operand::Number(204.0),
+            ])
+        );
+        assert_eq!(
+            parse("status_code in []").unwrap().value,
+            Operand::List(vec![])
+        );
+    }
+
+    #[test]
+    fn evaluates_facts() {
+        let facts = Facts::from([
+            ("status_code", Fact::Number(204.0)),
+            ("error", Fact::Text("connection reset".to_string())),
+            ("records", Fact::List(vec!["A.example.com".to_string()])),
+        ]);
+
+        assert!(parse("status_code in [200, 204]").unwrap().evaluate(&facts));
+        assert!(parse("status_code == '204'").unwrap().evaluate(&facts));
+        assert!(!parse("status_code > 204").unwrap().evaluate(&facts));
+        assert!(parse("error contains reset").unwrap().evaluate(&facts));
+        assert!(parse("records contains a.example.com")
+            .unwrap()
+            .evaluate(&facts));
+        // Missing fields always fail
+        assert!(!parse("latency_ms < 100").unwrap().evaluate(&facts));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::state;
+use chrono::{DateTime, Utc};
+use poem::handler;
+use poem::web::{Json, Query};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex};
+
+const RECORDS_STATE_KEY: &str = "dns_records";
+const CHANGES_STATE_KEY: &str = "dns_changes";
+
+/// Number of changes kept for the API, oldest are dropped first
+const MAX_RECENT_CHANGES: usize = 500;
+
+/// Record sets not seen for this long are forgotten. Probes land on whichever nodes are
+/// available, so most locations are only seen now and then.
+const RECORDS_RETENTION: chrono::Duration = chrono::Duration::days(1);
+
+/// How often record sets are checked against the retention
+const PRUNE_INTERVAL: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Last record set seen, keyed by [`RecordSetKey::id`]
+static LAST_RECORDS: LazyLock<Mutex<LastRecords>> = LazyLock::new(|| {
+    state::before_save(persist);
+    Mutex::new(LastRecords {
+        sets: state::get(RECORDS_STATE_KEY).unwrap_or_default(),
+        pruned: Utc::now(),
+    })
+});
+
+struct LastRecords {
+    sets: HashMap<String, RecordSet>,
+    pruned: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordSet {
+    seen: DateTime<Utc>,
+    records: BTreeSet<String>,
+}
+
+static RECENT_CHANGES: LazyLock<Mutex<VecDeque<DnsChange>>> =
+    LazyLock::new(|| Mutex::new(state::get(CHANGES_STATE_KEY).unwrap_or_default()));
+
+/// Identifies a record set: the same answer is expected for the same name, record type
+/// and vantage point
+pub struct RecordSetKey<'a> {
+    pub endpoint: &'a str,
+    pub record_type: &'static str,
+    pub location: String,
+}
+
+impl RecordSetKey<'_> {
+    fn id(&self) -> String {
+        format!("{}|{}|{}", self.endpoint, self.record_type, self.location)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DnsChange {
+    pub endpoint: String,
+    pub record_type: String,
+    pub location: String,
+    pub timestamp: DateTime<Utc>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Remembers the record set and returns the diff against the previous one, if it changed.
+///
+/// Records are compared case-insensitively and regardless of order. The first record set
+/// seen for a key is not a change.
+pub fn observe<T: AsRef<str>>(key: RecordSetKey, records: &[T]) -> Option<DnsChange> {
+    let current: BTreeSet<String> = records.iter().map(|r| r.as_ref().to_lowercase()).collect();
+    let now = Utc::now();
+
+    let mut last = LAST_RECORDS.lock().unwrap();
+    if now - last.pruned >= PRUNE_INTERVAL {
+        last.sets
+            .retain(|_, set| now - set.seen < RECORDS_RETENTION);
+        last.pruned = now;
+    }
+
+    let record_set = RecordSet {
+        seen: now,
+        records: current.clone(),
+    };
+    let previous = last.sets.insert(key.id(), record_set)?.records;
+    drop(last);
+
+    if previous == current {
+        return None;
+    }
+
+    let change = DnsChange {
+        endpoint: key.endpoint.to_string(),
+        record_type: key.record_type.to_string(),
+        location: key.location,
+        timestamp: now,
+        added: current.difference(&previous).cloned().collect(),
+        removed: previous.difference(&current).cloned().collect(),
+    };
+
+    let mut recent = RECENT_CHANGES.lock().unwrap();
+    if recent.len() >= MAX_RECENT_CHANGES {
+        recent.pop_front();
+    }
+    recent.push_back(change.clone());
+
+    Some(change)
+}
+
+/// Copies the last record sets and recent changes into the state snapshot
+fn persist() {
+    state::put(RECORDS_STATE_KEY, &LAST_RECORDS.lock().unwrap().sets);
+    state::put(CHANGES_STATE_KEY, &*RECENT_CHANGES.lock().unwrap());
+}
+
+#[derive(Deserialize)]
+pub struct ChangesQuery {
+    endpoint: Option<String>,
+    record_type: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Recent record set changes, newest first
+#[handler]
+pub fn recent_changes(Query(query): Query<ChangesQuery>) -> Json<Vec<DnsChange>> {
+    let recent = RECENT_CHANGES.lock().unwrap();
+
+    let changes = recent
+        .iter()
+        .rev()
+        .filter(|c| query.endpoint.as_ref().is_none_or(|e| *e == c.endpoint))
+        .filter(|c| {
+            query
+                .record_type
+                .as_ref()
+                .is_none_or(|t| *t == c.record_type)
+        })
+        .take(query.limit.unwrap_or(MAX_RECENT_CHANGES))
+        .cloned()
+        .collect();
+
+    Json(changes)
+}
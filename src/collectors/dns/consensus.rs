@@ -0,0 +1,213 @@
+use super::{is_bogon, is_private_address};
+use crate::asn;
+use crate::config::ConsensusConfig;
+use chrono::{DateTime, Utc};
+use poem::handler;
+use poem::web::{Json, Query};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{LazyLock, Mutex};
+
+/// Number of suspicious answers kept for the API, oldest are dropped first
+const MAX_SUSPICIOUS_ANSWERS: usize = 500;
+
+/// Latest answer from each location, keyed by endpoint and record type
+static ANSWERS: LazyLock<Mutex<HashMap<String, HashMap<String, LocationAnswer>>>> =
+    LazyLock::new(Default::default);
+
+static SUSPICIOUS: LazyLock<Mutex<VecDeque<SuspiciousAnswer>>> = LazyLock::new(Default::default);
+
+struct LocationAnswer {
+    timestamp: DateTime<Utc>,
+    records: BTreeSet<String>,
+    asns: BTreeSet<u32>,
+}
+
+/// A location's answer for an endpoint and record type
+pub struct Answer<'a> {
+    pub endpoint: &'a str,
+    pub record_type: &'static str,
+    pub location: String,
+    pub country_code: String,
+    pub isp: String,
+    /// Providers of the resolvers that gave the answer
+    pub dns_servers: Vec<String>,
+    pub records: &'a [String],
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviationReason {
+    /// Private, loopback or link-local address
+    Private,
+    /// Address that is never routed on the internet, e.g. documentation or reserved ranges
+    Bogon,
+    /// Address announced by an AS that most locations do not resolve to
+    OutsideConsensusAsn,
+    /// Record that most locations do not see
+    OutsideConsensus,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Deviation {
+    pub record: String,
+    pub reason: DeviationReason,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SuspiciousAnswer {
+    pub endpoint: String,
+    pub record_type: String,
+    pub location: String,
+    pub country_code: String,
+    pub isp: String,
+    pub dns_servers: Vec<String>,
+    pub timestamp: DateTime<Utc>,
+    pub records: Vec<String>,
+    /// Number of locations the consensus was built from
+    pub locations: usize,
+    pub deviations: Vec<Deviation>,
+}
+
+/// Adds the answer to the consensus and returns it as suspicious if any record deviates.
+///
+/// Addresses are compared by the AS announcing them, as CDNs hand out different addresses
+/// per region, so that comparison needs the ASN database. Other records are compared as is.
+pub fn observe(answer: Answer, config: &ConsensusConfig) -> Option<SuspiciousAnswer> {
+    let now = Utc::now();
+    let window = chrono::Duration::from_std(config.window).unwrap_or(chrono::Duration::MAX);
+
+    let records: BTreeSet<String> = answer
+        .records
+        .iter()
+        .map(|r| r.trim_end_matches('.').to_lowercase())
+        .collect();
+    let addresses: Vec<(&String, IpAddr)> = records
+        .iter()
+        .filter_map(|r| Some((r, IpAddr::from_str(r).ok()?)))
+        .collect();
+    let asns = addresses
+        .iter()
+        .filter_map(|(_, ip)| asn::lookup(*ip))
+        .map(|network| network.number)
+        .collect();
+
+    let mut all_answers = ANSWERS.lock().unwrap();
+    let answers = all_answers
+        .entry(format!("{}|{}", answer.endpoint, answer.record_type))
+        .or_default();
+
+    answers.retain(|_, seen| now - seen.timestamp < window);
+    answers.insert(
+        answer.location.clone(),
+        LocationAnswer {
+            timestamp: now,
+            records: records.clone(),
+            asns,
+        },
+    );
+
+    let locations = answers.len();
+    let in_consensus = |seen_from: usize| seen_from as f64 / locations as f64 >= config.share;
+    let compare = locations >= config.min_locations;
+
+    let mut deviations = Vec::new();
+
+    for (record, ip) in &addresses {
+        let reason = if is_private_address(ip) {
+            Some(DeviationReason::Private)
+        } else if is_bogon(ip) {
+            Some(DeviationReason::Bogon)
+        } else if compare {
+            asn::lookup(*ip)
+                .filter(|network| {
+                    let seen_from = answers
+                        .values()
+                        .filter(|seen| seen.asns.contains(&network.number))
+                        .count();
+                    !in_consensus(seen_from)
+                })
+                .map(|_| DeviationReason::OutsideConsensusAsn)
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            deviations.push(Deviation {
+                record: record.to_string(),
+                reason,
+            });
+        }
+    }
+
+    if compare {
+        for record in records.iter().filter(|r| IpAddr::from_str(r).is_err()) {
+            let seen_from = answers
+                .values()
+                .filter(|seen| seen.records.contains(record))
+                .count();
+
+            if !in_consensus(seen_from) {
+                deviations.push(Deviation {
+                    record: record.clone(),
+                    reason: DeviationReason::OutsideConsensus,
+                });
+            }
+        }
+    }
+    drop(all_answers);
+
+    if deviations.is_empty() {
+        return None;
+    }
+
+    let suspicious = SuspiciousAnswer {
+        endpoint: answer.endpoint.to_string(),
+        record_type: answer.record_type.to_string(),
+        location: answer.location,
+        country_code: answer.country_code,
+        isp: answer.isp,
+        dns_servers: answer.dns_servers,
+        timestamp: now,
+        records: records.into_iter().collect(),
+        locations,
+        deviations,
+    };
+
+    let mut recent = SUSPICIOUS.lock().unwrap();
+    if recent.len() >= MAX_SUSPICIOUS_ANSWERS {
+        recent.pop_front();
+    }
+    recent.push_back(suspicious.clone());
+
+    Some(suspicious)
+}
+
+#[derive(Deserialize)]
+pub struct SuspiciousQuery {
+    endpoint: Option<String>,
+    record_type: Option<String>,
+    country_code: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Recent answers that deviated from the consensus, newest first
+#[handler]
+pub fn suspicious_answers(Query(query): Query<SuspiciousQuery>) -> Json<Vec<SuspiciousAnswer>> {
+    let recent = SUSPICIOUS.lock().unwrap();
+    let matches = |filter: &Option<String>, value: &str| filter.as_ref().is_none_or(|f| f == value);
+
+    let answers = recent
+        .iter()
+        .rev()
+        .filter(|a| matches(&query.endpoint, &a.endpoint))
+        .filter(|a| matches(&query.record_type, &a.record_type))
+        .filter(|a| matches(&query.country_code, &a.country_code))
+        .take(query.limit.unwrap_or(MAX_SUSPICIOUS_ANSWERS))
+        .cloned()
+        .collect();
+
+    Json(answers)
+}
@@ -0,0 +1,298 @@
+use strum::{AsRefStr, EnumIter};
+
+/// SPF allows at most this many mechanisms that need a DNS lookup, RFC 7208 section 4.6.4
+pub const MAX_SPF_LOOKUPS: usize = 10;
+
+/// Name the DMARC policy of a domain is published at
+pub fn dmarc_name(domain: &str) -> String {
+    format!("_dmarc.{}", domain)
+}
+
+/// Name the DKIM key of a selector is published at
+pub fn dkim_name(selector: &str, domain: &str) -> String {
+    format!("{}._domainkey.{}", selector, domain)
+}
+
+/// TXT records may come quoted and split into several strings, which are joined back
+pub fn unquote_txt(record: &str) -> String {
+    let record = record.trim();
+
+    if record.starts_with('"') && record.ends_with('"') && record.len() >= 2 {
+        record[1..record.len() - 1]
+            .split("\" \"")
+            .flat_map(|part| part.split("\"\""))
+            .collect()
+    } else {
+        record.to_string()
+    }
+}
+
+/// The SPF record of a domain, parsed just enough to check it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spf {
+    pub record: String,
+    /// Mechanisms and modifiers that cost a DNS lookup. Nested includes are not followed,
+    /// so this is a lower bound of the real count.
+    pub lookups: usize,
+    /// Terms that are neither a known mechanism nor a modifier
+    pub invalid_terms: Vec<String>,
+}
+
+impl Spf {
+    /// Finds the SPF record among a domain's TXT records. More than one makes SPF fail
+    /// for receivers, which is reported as an error with the records found.
+    pub fn find(txt: &[String]) -> Option<Result<Spf, Vec<String>>> {
+        let records: Vec<String> = txt
+            .iter()
+            .map(|r| unquote_txt(r))
+            .filter(|r| {
+                let lower = r.to_ascii_lowercase();
+                lower == "v=spf1" || lower.starts_with("v=spf1 ")
+            })
+            .collect();
+
+        match records.as_slice() {
+            [] => None,
+            [record] => Some(Ok(Spf::parse(record))),
+            _ => Some(Err(records)),
+        }
+    }
+
+    fn parse(record: &str) -> Spf {
+        let mut lookups = 0;
+        let mut invalid_terms = Vec::new();
+
+        for term in record.split_whitespace().skip(1) {
+            let lower = term.to_ascii_lowercase();
+            let mechanism = lower.trim_start_matches(['+', '-', '~', '?']);
+            let name = mechanism.split([':', '/', '=']).next().unwrap_or_default();
+
+            match name {
+                "include" | "a" | "mx" | "ptr" | "exists" => lookups += 1,
+                "redirect" if mechanism.starts_with("redirect=") => lookups += 1,
+                "all" | "ip4" | "ip6" => {}
+                // Unknown modifiers are allowed and ignored by receivers
+                _ if mechanism == lower && mechanism.contains('=') => {}
+                _ => invalid_terms.push(term.to_string()),
+            }
+        }
+
+        Spf {
+            record: record.to_string(),
+            lookups,
+            invalid_terms,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.invalid_terms.is_empty() && self.lookups <= MAX_SPF_LOOKUPS
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum DmarcPolicy {
+    None,
+    Quarantine,
+    Reject,
+    /// No DMARC record, or one without a valid `p` tag
+    Missing,
+}
+
+/// The DMARC record of a domain, with the tags that matter for enforcement
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dmarc {
+    pub record: String,
+    pub policy: DmarcPolicy,
+    /// Policy for subdomains, the domain's own policy when not set
+    pub subdomain_policy: DmarcPolicy,
+    /// Percentage of failing mail the policy applies to
+    pub percent: u8,
+    pub reports: bool,
+}
+
+impl Dmarc {
+    /// Finds the DMARC record among the TXT records at `_dmarc`. Receivers ignore the
+    /// policy when more than one record is published.
+    pub fn find(txt: &[String]) -> Option<Dmarc> {
+        let records: Vec<String> = txt
+            .iter()
+            .map(|r| unquote_txt(r))
+            .filter(|r| r.to_ascii_lowercase().starts_with("v=dmarc1"))
+            .collect();
+
+        match records.as_slice() {
+            [record] => Some(Dmarc::parse(record)),
+            _ => None,
+        }
+    }
+
+    fn parse(record: &str) -> Dmarc {
+        let tag = |name: &str| {
+            record.split(';').find_map(|tag| {
+                let (key, value) = tag.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case(name)
+                    .then(|| value.trim().to_ascii_lowercase())
+            })
+        };
+        let policy = |value: Option<String>| match value.as_deref() {
+            Some("none") => DmarcPolicy::None,
+            Some("quarantine") => DmarcPolicy::Quarantine,
+            Some("reject") => DmarcPolicy::Reject,
+            _ => DmarcPolicy::Missing,
+        };
+
+        let domain_policy = policy(tag("p"));
+        let subdomain_policy = match tag("sp") {
+            Some(sp) => policy(Some(sp)),
+            None => domain_policy,
+        };
+
+        Dmarc {
+            record: record.to_string(),
+            policy: domain_policy,
+            subdomain_policy,
+            percent: tag("pct").and_then(|p| p.parse().ok()).unwrap_or(100),
+            reports: tag("rua").is_some_and(|rua| !rua.is_empty()),
+        }
+    }
+}
+
+/// Whether the TXT records at a DKIM selector publish a key. An empty `p=` tag means the
+/// key was revoked.
+pub fn dkim_key_present(txt: &[String]) -> bool {
+    txt.iter().map(|r| unquote_txt(r)).any(|record| {
+        record.split(';').any(|tag| {
+            tag.split_once('=')
+                .is_some_and(|(key, value)| key.trim() == "p" && !value.trim().is_empty())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txt(records: &[&str]) -> Vec<String> {
+        records.iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn joins_quoted_and_split_txt() {
+        assert_eq!(unquote_txt("v=spf1 -all"), "v=spf1 -all");
+        assert_eq!(unquote_txt(" \"v=spf1 -all\" "), "v=spf1 -all");
+        assert_eq!(
+            unquote_txt("\"v=spf1 include:_spf.example.com\" \" -all\""),
+            "v=spf1 include:_spf.example.com -all"
+        );
+        assert_eq!(unquote_txt("\"v=spf1 \"\"-all\""), "v=spf1 -all");
+    }
+
+    #[test]
+    fn finds_the_spf_record() {
+        assert_eq!(Spf::find(&txt(&["google-site-verification=abc"])), None);
+
+        let spf = Spf::find(&txt(&[
+            "google-site-verification=abc",
+            "\"v=spf1 include:_spf.example.com\" \" -all\"",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(spf.record, "v=spf1 include:_spf.example.com -all");
+        assert_eq!(spf.lookups, 1);
+        assert!(spf.is_valid());
+
+        // Not a prefix match on the version
+        assert_eq!(Spf::find(&txt(&["v=spf10 -all"])), None);
+        assert!(Spf::find(&txt(&["V=SPF1 -all"])).is_some());
+    }
+
+    #[test]
+    fn multiple_spf_records_are_an_error() {
+        let found = Spf::find(&txt(&["v=spf1 mx -all", "\"v=spf1 a -all\""]));
+        assert_eq!(
+            found,
+            Some(Err(vec!["v=spf1 mx -all".into(), "v=spf1 a -all".into()]))
+        );
+    }
+
+    #[test]
+    fn counts_lookups_with_qualifiers() {
+        let spf = Spf::parse("v=spf1 +a -mx:mail.example.com/24 ~include:a.example ?exists:%{i}.x ptr ip4:192.0.2.0/24 ip6:2001:db8::/32 redirect=b.example");
+        assert_eq!(spf.lookups, 6);
+        assert!(spf.invalid_terms.is_empty());
+        assert!(spf.is_valid());
+    }
+
+    #[test]
+    fn too_many_lookups_is_invalid() {
+        let includes: Vec<String> = (0..=MAX_SPF_LOOKUPS)
+            .map(|i| format!("include:{}.example.com", i))
+            .collect();
+        let spf = Spf::parse(&format!("v=spf1 {} -all", includes.join(" ")));
+        assert_eq!(spf.lookups, MAX_SPF_LOOKUPS + 1);
+        assert!(!spf.is_valid());
+
+        let spf = Spf::parse(&format!("v=spf1 {} -all", includes[1..].join(" ")));
+        assert!(spf.is_valid());
+    }
+
+    #[test]
+    fn unknown_modifiers_are_allowed() {
+        let spf = Spf::parse("v=spf1 mx exp=explain.example.com foo=bar -all");
+        assert_eq!(spf.lookups, 1);
+        assert!(spf.is_valid());
+
+        // Modifiers take no qualifier and unknown mechanisms are errors
+        let spf = Spf::parse("v=spf1 -foo=bar bogus ~all");
+        assert_eq!(spf.invalid_terms, vec!["-foo=bar", "bogus"]);
+        assert!(!spf.is_valid());
+    }
+
+    #[test]
+    fn parses_dmarc_tags() {
+        let dmarc = Dmarc::find(&txt(&[
+            "\"v=DMARC1; p=reject; sp=none; \" \"pct=50; rua=mailto:dmarc@example.com\"",
+        ]))
+        .unwrap();
+        assert_eq!(dmarc.policy, DmarcPolicy::Reject);
+        assert_eq!(dmarc.subdomain_policy, DmarcPolicy::None);
+        assert_eq!(dmarc.percent, 50);
+        assert!(dmarc.reports);
+
+        let dmarc = Dmarc::find(&txt(&["V=DMARC1; P=Quarantine"])).unwrap();
+        assert_eq!(dmarc.policy, DmarcPolicy::Quarantine);
+        assert_eq!(dmarc.subdomain_policy, DmarcPolicy::Quarantine);
+        assert_eq!(dmarc.percent, 100);
+        assert!(!dmarc.reports);
+    }
+
+    #[test]
+    fn invalid_or_missing_dmarc() {
+        let dmarc = Dmarc::find(&txt(&["v=DMARC1; p=block; pct=x; rua="])).unwrap();
+        assert_eq!(dmarc.policy, DmarcPolicy::Missing);
+        assert_eq!(dmarc.percent, 100);
+        assert!(!dmarc.reports);
+
+        assert_eq!(Dmarc::find(&txt(&["some other record"])), None);
+        assert_eq!(
+            Dmarc::find(&txt(&["v=DMARC1; p=reject", "v=DMARC1; p=none"])),
+            None
+        );
+    }
+
+    #[test]
+    fn detects_dkim_keys() {
+        assert!(dkim_key_present(&txt(&[
+            "v=DKIM1; k=rsa; p=MIGfMA0GCSqGSIb3"
+        ])));
+        assert!(dkim_key_present(&txt(&[
+            "\"v=DKIM1; k=rsa; \" \"p=MIGfMA0GCSqGSIb3\""
+        ])));
+        // An empty key is a revoked one
+        assert!(!dkim_key_present(&txt(&["v=DKIM1; k=rsa; p="])));
+        assert!(!dkim_key_present(&txt(&["v=DKIM1; k=rsa"])));
+        assert!(!dkim_key_present(&[]));
+    }
+}
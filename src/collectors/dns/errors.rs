@@ -9,6 +9,7 @@ pub enum DnsErrorType {
     Other(String),
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DnsRecordType {
     A,
@@ -22,18 +23,32 @@ pub enum DnsRecordType {
     Unknown,
 }
 
-impl std::fmt::Display for DnsRecordType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl DnsErrorType {
+    /// Broad category of the error, used as the `error_category` label
+    pub fn category(&self) -> &'static str {
         match self {
-            DnsRecordType::A => write!(f, "A"),
-            DnsRecordType::AAAA => write!(f, "AAAA"),
-            DnsRecordType::MX => write!(f, "MX"),
-            DnsRecordType::TXT => write!(f, "TXT"),
-            DnsRecordType::NS => write!(f, "NS"),
-            DnsRecordType::SOA => write!(f, "SOA"),
-            DnsRecordType::SRV => write!(f, "SRV"),
-            DnsRecordType::TLSA => write!(f, "TLSA"),
-            DnsRecordType::Unknown => write!(f, "UNKNOWN"),
+            DnsErrorType::NoRecord(_) => "no_record",
+            DnsErrorType::NetworkError(_) => "network",
+            DnsErrorType::ConfigurationError(_) => "config",
+            DnsErrorType::Timeout(_) => "timeout",
+            DnsErrorType::Other(_) => "other",
+        }
+    }
+}
+
+impl DnsRecordType {
+    /// `record_type` label value, in the vocabulary of the lookup types so error series line
+    /// up with the per-record series. A and AAAA are both answers to an `ip` lookup.
+    pub fn label(&self) -> &'static str {
+        match self {
+            DnsRecordType::A | DnsRecordType::AAAA => "ip",
+            DnsRecordType::MX => "mx",
+            DnsRecordType::TXT => "txt",
+            DnsRecordType::NS => "ns",
+            DnsRecordType::SOA => "soa",
+            DnsRecordType::SRV => "srv",
+            DnsRecordType::TLSA => "tlsa",
+            DnsRecordType::Unknown => "unknown",
         }
     }
 }
@@ -69,6 +84,11 @@ impl DnsErrorParser {
                 continue;
             }
 
+            if let Some(error) = Self::parse_configuration_error(trimmed) {
+                errors.push(DnsErrorType::ConfigurationError(error));
+                continue;
+            }
+
             errors.push(DnsErrorType::Other(trimmed.to_string()));
         }
 
@@ -103,7 +123,22 @@ impl DnsErrorParser {
     }
 
     fn parse_network_error(line: &str) -> Option<String> {
-        if line.contains("network error") || line.contains("connection refused") {
+        if line.contains("network error")
+            || line.contains("connection refused")
+            || line.contains("network is unreachable")
+            || line.contains("server misbehaving")
+        {
+            Some(line.to_string())
+        } else {
+            None
+        }
+    }
+
+    fn parse_configuration_error(line: &str) -> Option<String> {
+        if line.contains("no nameservers")
+            || line.contains("resolv.conf")
+            || line.contains("invalid name server")
+        {
             Some(line.to_string())
         } else {
             None
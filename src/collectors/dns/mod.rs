@@ -1,34 +1,85 @@
+pub mod changes;
+pub mod consensus;
+mod email;
 mod errors;
-
-use super::{Collector, CollectorErrors};
-use crate::config::{DnsConfig, LookupTypes};
+pub mod providers;
+mod records;
+mod soa;
+
+use super::{Collector, CollectorErrors, ProbeOutcome};
+use crate::asn;
+use crate::assertions::{probe_facts, Fact, Facts};
+use crate::config::{DnsConfig, EmailAuthConfig, ExpectedAnswer, LookupTypes, MetricConfig};
 use crate::types::{
     PerformDnsBodyConfiguration, PerformDnsBodyConfigurationLookupTypesItem,
     PerformDnsBodyContinentCode, PerformDnsBodyCountryCode, PerformDnsBodyMobile,
     PerformDnsBodyProxy, PerformDnsBodyResidential, PerformDnsResponse,
-    PerformDnsResponseResultsItemResult,
+    PerformDnsResponseResultsItem, PerformDnsResponseResultsItemResult,
 };
 use crate::API_CLIENT;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
+use errors::{DnsErrorParser, DnsErrorType, DnsRecordType};
 use geohash::Coord;
 use metrics::{counter, gauge, histogram};
 use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
-use std::hash::{Hash, Hasher};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::str::FromStr;
+use std::time::SystemTime;
+use strum::IntoEnumIterator;
 use tracing::{error, info, warn};
 
 pub struct DnsCollector {
     config: &'static DnsConfig,
+    dns_servers: Vec<String>,
+    lookup_types: Vec<LookupTypes>,
+    record_series: records::RecordSeriesCap,
 }
 
 impl Collector for DnsCollector {
     type Config = DnsConfig;
     type Response = PerformDnsResponse;
 
+    const PROTOCOL: &'static str = "dns";
+
     fn new(config: &'static DnsConfig) -> Self {
-        Self { config }
+        let mut lookup_types = Vec::new();
+        for lookup_type in config.lookup_type.iter().chain(&config.lookup_types) {
+            if !lookup_types.contains(lookup_type) {
+                lookup_types.push(*lookup_type);
+            }
+        }
+        // Mail domains do not need an address, so only MX and TXT are added
+        if config.email_auth.is_some() {
+            for lookup_type in [LookupTypes::MX, LookupTypes::TXT] {
+                if !lookup_types.contains(&lookup_type) {
+                    lookup_types.push(lookup_type);
+                }
+            }
+        }
+        // Hijacked NXDOMAIN answers point at ad servers, so only addresses are looked up
+        if lookup_types.is_empty() || config.nxdomain_canary {
+            lookup_types = vec![LookupTypes::IP];
+        }
+
+        Self {
+            config,
+            // Presets were checked on boot, but may have been removed from the catalog since
+            dns_servers: resolve_dns_servers(&config.dns_servers).unwrap_or_else(|e| {
+                error!(%e, "Falling back to the node's own resolvers");
+                Vec::new()
+            }),
+            lookup_types,
+            record_series: records::RecordSeriesCap::new(
+                config.max_record_series,
+                crate::CONFIG.global_config.metric_clear_timeout,
+            ),
+        }
+    }
+
+    fn common_config(&self) -> &'static MetricConfig {
+        &self.config.common_config
     }
 
     fn register_metrics(&self) {
@@ -46,11 +97,141 @@ impl Collector for DnsCollector {
 
         metrics::describe_counter!(format!("{}dns_lookup_total", prefix), "Total DNS lookups");
 
+        metrics::describe_gauge!(
+            format!("{}dns_answer_match", prefix),
+            "Whether the answer matched the expected records"
+        );
+
+        metrics::describe_counter!(
+            format!("{}dns_answer_mismatch_total", prefix),
+            "Number of answers that did not match the expected records"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_soa_serial", prefix),
+            "SOA serial seen from the location"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_soa_serial_highest", prefix),
+            "Highest SOA serial seen from any location"
+        );
+
+        metrics::describe_histogram!(
+            format!("{}dns_soa_propagation_lag_seconds", prefix),
+            "Time from a new SOA serial first being seen to each location seeing it"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_resolver_asn_info", prefix),
+            "Autonomous system of the resolvers the node used, from the ASN database"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_answer_asn_info", prefix),
+            "Autonomous system of the addresses the endpoint resolved to, from the ASN database"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_answer_deviation", prefix),
+            "Whether the answer from the location deviates from the consensus of all locations"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_nxdomain_hijack", prefix),
+            "Whether the resolver answered a name that does not exist instead of returning NXDOMAIN"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_email_mx_count", prefix),
+            "Number of MX records of the mail domain"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_email_spf_present", prefix),
+            "Whether the mail domain publishes an SPF record"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_email_spf_valid", prefix),
+            "Whether the SPF record is unique, well formed and within the DNS lookup limit"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_email_spf_lookups", prefix),
+            "DNS lookups the SPF record needs, not counting nested includes"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_email_dmarc_policy", prefix),
+            "Whether the DMARC policy of the mail domain is the one in the policy label"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_email_dmarc_subdomain_policy", prefix),
+            "Whether the DMARC policy for subdomains is the one in the policy label"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_email_dmarc_percent", prefix),
+            "Percentage of failing mail the DMARC policy applies to"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_email_dmarc_reports", prefix),
+            "Whether the DMARC record asks for aggregate reports"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_email_dkim_key_present", prefix),
+            "Whether the DKIM selector publishes a key"
+        );
+
+        metrics::describe_counter!(
+            format!("{}dns_email_auth_changes_total", prefix),
+            "Number of times the SPF, DMARC or DKIM records seen from a location changed"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_mx_record_info", prefix),
+            "MX record returned to the location, with its preference and exchange"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_srv_record_info", prefix),
+            "SRV record returned to the location, with its priority, weight, port and target"
+        );
+
+        metrics::describe_gauge!(
+            format!("{}dns_tlsa_record_info", prefix),
+            "TLSA record returned to the location, with its usage, selector, matching type and a digest of its data"
+        );
+
+        metrics::describe_counter!(
+            format!("{}dns_record_info_dropped_total", prefix),
+            "Records left out of the info series because the cap of distinct records was reached"
+        );
+
+        metrics::describe_counter!(
+            format!("{}dns_record_changes_total", prefix),
+            "Number of times the record set seen from a location changed"
+        );
+
         metrics::describe_histogram!(
             format!("{}dns_server_lookup_duration_ms", prefix),
             "Time taken to perform DNS lookup in ms"
         );
 
+        metrics::describe_histogram!(
+            format!("{}dns_uncached_lookup_duration_ms", prefix),
+            "Time taken to resolve a random name under the cache busting domain in ms"
+        );
+
+        metrics::describe_counter!(
+            format!("{}dns_uncached_lookup_error_total", prefix),
+            "Number of lookups under the cache busting domain that failed"
+        );
+
         metrics::describe_gauge!(
             format!("{}dns_record_hash", prefix),
             "Records the hash of the response the node saw"
@@ -128,7 +309,7 @@ impl Collector for DnsCollector {
         let response = API_CLIENT
             .perform_dns()
             .body_map(|body| {
-                body.hostnames([self.config.common_config.endpoint.clone()])
+                body.hostnames(self.hostnames())
                     .country_code(country_code)
                     .continent_code(continent_code)
                     .mobile(mobile)
@@ -137,11 +318,15 @@ impl Collector for DnsCollector {
                     .node_id(node_id)
                     .proxy(proxy)
                     .configuration(Some(PerformDnsBodyConfiguration {
-                        dns_servers: vec![],
-                        lookup_types: vec![PerformDnsBodyConfigurationLookupTypesItem::from_str(
-                            self.config.lookup_type.as_ref(),
-                        )
-                        .unwrap()],
+                        dns_servers: self.dns_servers.clone(),
+                        lookup_types: self
+                            .lookup_types
+                            .iter()
+                            .map(|t| {
+                                PerformDnsBodyConfigurationLookupTypesItem::from_str(t.as_ref())
+                                    .unwrap()
+                            })
+                            .collect(),
                     }))
             })
             .send()
@@ -154,7 +339,39 @@ impl Collector for DnsCollector {
         self.config.common_config.frequency
     }
 
-    fn handle_response(&self, response: PerformDnsResponse) -> Result<(), CollectorErrors> {
+    fn facts(&self, response: &PerformDnsResponse) -> Facts {
+        let Some(result) = self.endpoint_result(&response.results) else {
+            return Facts::new();
+        };
+        let mut facts = probe_facts(result.duration, result.error.as_ref());
+
+        if let Some(dns_result) = &result.result {
+            let mut all_records = Vec::new();
+            for (record_type, records) in self.lookups(dns_result) {
+                facts.insert(record_type, Fact::List(records.to_vec()));
+                all_records.extend_from_slice(records);
+            }
+
+            facts.insert("records_count", Fact::Number(all_records.len() as f64));
+            facts.insert("records", Fact::List(all_records));
+            facts.insert("dns_servers", Fact::List(dns_result.dns_servers.clone()));
+
+            if let Some(soa) = dns_result
+                .soa
+                .iter()
+                .find_map(|r| r.parse::<soa::Soa>().ok())
+            {
+                facts.insert("soa_serial", Fact::Number(soa.serial as f64));
+            }
+        }
+
+        facts
+    }
+
+    fn handle_response(
+        &self,
+        response: PerformDnsResponse,
+    ) -> Result<ProbeOutcome, CollectorErrors> {
         let endpoint = self
             .config
             .common_config
@@ -185,27 +402,62 @@ impl Collector for DnsCollector {
             labels.insert("geohash", v);
         }
 
-        if let Some(result) = response.results.first() {
+        if self.config.nxdomain_canary {
+            let result = response
+                .results
+                .first()
+                .ok_or_else(|| CollectorErrors::MissingData(endpoint.clone(), "no_results"))?;
+            return Ok(self.check_nxdomain_canary(result, &labels));
+        }
+
+        if let Some(result) = self.uncached_result(&response.results) {
+            self.record_uncached_lookup(result, &labels);
+        }
+        if let Some(email_auth) = &self.config.email_auth {
+            self.check_email_auth(email_auth, &response.results, &labels);
+        }
+
+        let outcome = if let Some(result) = self.endpoint_result(&response.results) {
             if let Some(error) = &result.error {
-                // Handle error case
                 self.record_failure_with_labels(error, &labels);
-            } else if let Some(dns_result) = &result.result {
-                // Handle success case
-                let cleaned_dns_ips = dns_result
-                    .dns_servers
-                    .iter()
-                    .map(|s| s.replace("udp:", "").replace("tcp:", "").replace(":53", ""));
+            }
 
-                let dns_providers = identify_dns_providers(cleaned_dns_ips);
+            if let Some(dns_result) = &result.result {
+                let answered = Self::answered_lookups(&self.lookup_types, result);
+                let dns_providers = dns_server_providers(dns_result);
 
                 for server in dns_providers {
                     labels.insert("dns_server", server);
                     self.record_success_metrics(
                         dns_result,
+                        &answered,
                         result.duration.unwrap_or(0.0),
                         &labels,
                     );
                 }
+                labels.remove("dns_server");
+
+                self.record_networks(dns_result, &labels);
+                self.record_structured(dns_result, &labels);
+                self.detect_changes(dns_result, &answered, &labels);
+                self.check_consensus(dns_result, &answered, &labels);
+                if answered.contains(&LookupTypes::SOA) {
+                    self.track_soa_serial(dns_result, &labels);
+                }
+                let answer_matches = self.check_expected_answer(dns_result, &answered, &labels);
+
+                // A lookup only counts as successful if every record type returned the
+                // expected records
+                let any_empty = self
+                    .lookups(dns_result)
+                    .any(|(_, records)| records.is_empty());
+                if any_empty || !answer_matches {
+                    ProbeOutcome::Failure
+                } else {
+                    ProbeOutcome::Success
+                }
+            } else if result.error.is_some() {
+                ProbeOutcome::Failure
             } else {
                 error!("Missing DNS result data");
                 return Err(CollectorErrors::MissingData(endpoint.clone(), "dns_result"));
@@ -213,19 +465,340 @@ impl Collector for DnsCollector {
         } else {
             error!("No results returned from API");
             return Err(CollectorErrors::MissingData(endpoint.clone(), "no_results"));
-        }
+        };
 
-        Ok(())
+        Ok(outcome)
     }
 }
 
 impl DnsCollector {
+    /// Names sent to the nodes: a fresh random label under the endpoint for canary checks,
+    /// otherwise the endpoint and a random label under the cache busting domain, if any
+    fn hostnames(&self) -> Vec<String> {
+        let endpoint = &self.config.common_config.endpoint;
+
+        if self.config.nxdomain_canary {
+            return vec![format!("{}.{}", random_label(), endpoint)];
+        }
+
+        let mut hostnames = vec![endpoint.clone()];
+        if let Some(domain) = &self.config.cache_busting_domain {
+            hostnames.push(format!("{}.{}", random_label(), domain));
+        }
+        if let Some(email_auth) = &self.config.email_auth {
+            hostnames.push(email::dmarc_name(endpoint));
+            hostnames.extend(
+                email_auth
+                    .dkim_selectors
+                    .iter()
+                    .map(|selector| email::dkim_name(selector, endpoint)),
+            );
+        }
+        hostnames
+    }
+
+    /// Result for the configured endpoint, as opposed to the cache busting lookup
+    fn endpoint_result<'a>(
+        &self,
+        results: &'a [PerformDnsResponseResultsItem],
+    ) -> Option<&'a PerformDnsResponseResultsItem> {
+        if self.config.cache_busting_domain.is_none() && self.config.email_auth.is_none() {
+            return results.first();
+        }
+
+        results
+            .iter()
+            .find(|r| r.endpoint == self.config.common_config.endpoint)
+    }
+
+    fn uncached_result<'a>(
+        &self,
+        results: &'a [PerformDnsResponseResultsItem],
+    ) -> Option<&'a PerformDnsResponseResultsItem> {
+        let domain = self.config.cache_busting_domain.as_ref()?;
+
+        results
+            .iter()
+            .find(|r| r.endpoint.ends_with(&format!(".{}", domain)))
+    }
+
+    /// Exports the mail domain's MX, SPF, DMARC and DKIM posture as seen from the location
+    fn check_email_auth(
+        &self,
+        config: &EmailAuthConfig,
+        results: &[PerformDnsResponseResultsItem],
+        labels: &HashMap<&'static str, String>,
+    ) {
+        let prefix = &self.config.common_config.prefix;
+        let domain = &self.config.common_config.endpoint;
+
+        let answer = |name: &str| results.iter().find(|r| r.endpoint == name);
+        // None when the name was not looked up or the lookup failed, so a transient error
+        // leaves the gauges and the tracked record sets as they were
+        let txt =
+            |name: &str| answered_records(answer(name)?, DnsRecordType::TXT, |r| r.txt.as_slice());
+
+        let mx = answer(domain)
+            .and_then(|item| answered_records(item, DnsRecordType::MX, |r| r.mx.as_slice()));
+        if let Some(mx) = mx {
+            gauge!(format!("{}dns_email_mx_count", prefix), labels).set(mx.len() as f64);
+        }
+
+        let spf = txt(domain).map(email::Spf::find);
+        if let Some(spf) = &spf {
+            let spf_valid = matches!(spf, Some(Ok(spf)) if spf.is_valid());
+            let spf_lookups = match spf {
+                Some(Ok(spf)) => spf.lookups,
+                _ => 0,
+            };
+            gauge!(format!("{}dns_email_spf_present", prefix), labels)
+                .set(spf.is_some() as u8 as f64);
+            gauge!(format!("{}dns_email_spf_valid", prefix), labels).set(spf_valid as u8 as f64);
+            gauge!(format!("{}dns_email_spf_lookups", prefix), labels).set(spf_lookups as f64);
+        }
+
+        let dmarc = txt(&email::dmarc_name(domain)).map(email::Dmarc::find);
+        if let Some(dmarc) = &dmarc {
+            let (policy, subdomain_policy) = dmarc.as_ref().map_or(
+                (email::DmarcPolicy::Missing, email::DmarcPolicy::Missing),
+                |d| (d.policy, d.subdomain_policy),
+            );
+            for candidate in email::DmarcPolicy::iter() {
+                let mut policy_labels = labels.clone();
+                policy_labels.insert("policy", candidate.as_ref().to_string());
+                gauge!(format!("{}dns_email_dmarc_policy", prefix), &policy_labels)
+                    .set((candidate == policy) as u8 as f64);
+                gauge!(
+                    format!("{}dns_email_dmarc_subdomain_policy", prefix),
+                    &policy_labels
+                )
+                .set((candidate == subdomain_policy) as u8 as f64);
+            }
+
+            let percent = dmarc.as_ref().map_or(0, |d| d.percent);
+            let reports = dmarc.as_ref().is_some_and(|d| d.reports);
+            gauge!(format!("{}dns_email_dmarc_percent", prefix), labels).set(percent as f64);
+            gauge!(format!("{}dns_email_dmarc_reports", prefix), labels).set(reports as u8 as f64);
+        }
+
+        // Selectors are tracked as one record set, so it is only compared when every
+        // selector could be looked up
+        let mut dkim = Some(Vec::new());
+        for selector in &config.dkim_selectors {
+            let Some(txt) = txt(&email::dkim_name(selector, domain)) else {
+                dkim = None;
+                continue;
+            };
+
+            let present = email::dkim_key_present(txt);
+            let state = if present { "key" } else { "none" };
+            if let Some(dkim) = &mut dkim {
+                dkim.push(format!("{}: {}", selector, state));
+            }
+
+            let mut selector_labels = labels.clone();
+            selector_labels.insert("selector", selector.clone());
+            gauge!(
+                format!("{}dns_email_dkim_key_present", prefix),
+                &selector_labels
+            )
+            .set(present as u8 as f64);
+        }
+
+        let spf_invalid = spf
+            .as_ref()
+            .is_some_and(|spf| !matches!(spf, Some(Ok(spf)) if spf.is_valid()));
+        let dmarc_missing = dmarc.as_ref().is_some_and(|dmarc| {
+            dmarc
+                .as_ref()
+                .is_none_or(|d| d.policy == email::DmarcPolicy::Missing)
+        });
+        if spf_invalid || dmarc_missing {
+            warn!(
+                endpoint = %domain,
+                country_code = labels.get("country_code"),
+                isp = labels.get("isp"),
+                ?spf,
+                ?dmarc,
+                "Mail domain has no valid SPF record or DMARC policy"
+            );
+        }
+
+        // Changes go through the same record set tracking as the lookups themselves, so
+        // they are also listed by the changes API
+        let spf_records = spf.map(|spf| match spf {
+            Some(Ok(spf)) => vec![spf.record],
+            Some(Err(records)) => records,
+            None => Vec::new(),
+        });
+        let dmarc_records = dmarc.map(|dmarc| dmarc.into_iter().map(|d| d.record).collect());
+
+        for (component, records) in [
+            ("spf", spf_records),
+            ("dmarc", dmarc_records),
+            ("dkim", dkim),
+        ] {
+            let Some(records) = records else {
+                continue;
+            };
+
+            let key = changes::RecordSetKey {
+                endpoint: labels
+                    .get("endpoint")
+                    .map(String::as_str)
+                    .unwrap_or_default(),
+                record_type: component,
+                location: location(labels),
+            };
+
+            if let Some(change) = changes::observe(key, &records) {
+                info!(
+                    endpoint = %change.endpoint,
+                    component,
+                    location = %change.location,
+                    added = ?change.added,
+                    removed = ?change.removed,
+                    "Mail authentication records changed"
+                );
+
+                let mut change_labels = labels.clone();
+                change_labels.insert("component", component.into());
+                counter!(
+                    format!("{}dns_email_auth_changes_total", prefix),
+                    &change_labels
+                )
+                .increment(1);
+            }
+        }
+    }
+
+    /// Resolution time of a name no resolver has cached, so the full recursion down to the
+    /// authoritative servers, kept apart from the usual, mostly cached, lookup duration
+    fn record_uncached_lookup(
+        &self,
+        result: &PerformDnsResponseResultsItem,
+        labels: &HashMap<&'static str, String>,
+    ) {
+        let prefix = &self.config.common_config.prefix;
+
+        let Some(dns_result) = result.result.as_ref().filter(|_| result.error.is_none()) else {
+            warn!(
+                endpoint = %result.endpoint,
+                error = ?result.error,
+                "Cache busting lookup failed"
+            );
+            counter!(format!("{}dns_uncached_lookup_error_total", prefix), labels).increment(1);
+            return;
+        };
+
+        // A zero sample would drag the percentiles down, so lookups without a duration are left out
+        let Some(duration) = result.duration else {
+            warn!(endpoint = %result.endpoint, "Cache busting lookup has no duration");
+            return;
+        };
+
+        for server in dns_server_providers(dns_result) {
+            let mut uncached_labels = labels.clone();
+            uncached_labels.insert("dns_server", server);
+            histogram!(
+                format!("{}dns_uncached_lookup_duration_ms", prefix),
+                &uncached_labels
+            )
+            .record(duration);
+        }
+    }
+
+    /// A canary name must not resolve: NXDOMAIN is the success case, any address is a
+    /// resolver rewriting it
+    fn check_nxdomain_canary(
+        &self,
+        result: &PerformDnsResponseResultsItem,
+        labels: &HashMap<&'static str, String>,
+    ) -> ProbeOutcome {
+        let prefix = &self.config.common_config.prefix;
+        let ips = result.result.as_ref().map_or(&[][..], |r| r.ips.as_slice());
+
+        if let Some(error) = &result.error {
+            // An error nothing could be parsed from is not an NXDOMAIN
+            let errors = DnsErrorParser::parse(error);
+            let nxdomain = !errors.is_empty()
+                && errors
+                    .iter()
+                    .all(|e| matches!(e, DnsErrorType::NoRecord(_)));
+
+            if !nxdomain {
+                self.record_failure_with_labels(error, labels);
+                return ProbeOutcome::Failure;
+            }
+        }
+
+        let dns_servers = result
+            .result
+            .as_ref()
+            .map(dns_server_providers)
+            .filter(|providers| !providers.is_empty())
+            .unwrap_or_else(|| HashSet::from([UNKNOWN_PROVIDER.to_string()]));
+
+        let hijacked = !ips.is_empty();
+        for server in dns_servers {
+            let mut canary_labels = labels.clone();
+            canary_labels.insert("dns_server", server);
+            gauge!(format!("{}dns_nxdomain_hijack", prefix), &canary_labels)
+                .set(hijacked as u8 as f64);
+        }
+
+        if hijacked {
+            warn!(
+                endpoint = %self.config.common_config.endpoint,
+                country_code = labels.get("country_code"),
+                isp = labels.get("isp"),
+                ?ips,
+                "Resolver answered a name that does not exist, NXDOMAIN is being hijacked"
+            );
+            ProbeOutcome::Failure
+        } else {
+            ProbeOutcome::Success
+        }
+    }
+
+    /// Records one error series per line of the error, as a lookup of several record
+    /// types can fail for different reasons at once
     fn record_failure_with_labels(&self, error: &str, labels: &HashMap<&'static str, String>) {
-        let mut labels = labels.clone();
-        let error_type = match error {
+        let mut errors = DnsErrorParser::parse(error);
+        if errors.is_empty() {
+            errors.push(DnsErrorType::Other(error.to_string()));
+        }
+
+        for parsed in errors {
+            let mut labels = labels.clone();
+            labels.insert("error_category", parsed.category().into());
+
+            let line = match &parsed {
+                DnsErrorType::NoRecord(record_type) => {
+                    labels.insert("record_type", record_type.label().into());
+                    "no record found for Query"
+                }
+                DnsErrorType::NetworkError(line)
+                | DnsErrorType::ConfigurationError(line)
+                | DnsErrorType::Timeout(line)
+                | DnsErrorType::Other(line) => line,
+            };
+            labels.insert("error_type", Self::error_type(line).into());
+
+            counter!(
+                format!("{}dns_lookup_error_total", self.config.common_config.prefix),
+                &labels
+            )
+            .increment(1);
+        }
+    }
+
+    /// Finer grained classification of a single error line, kept for existing dashboards
+    fn error_type(line: &str) -> &'static str {
+        match line {
             e if e.contains("no record found for Query") => "no_records",
             e if e.contains("connection refused") => "connection_refused",
-            e if e.contains("connection timed out") => "timeout",
+            e if e.contains("timed out") => "timeout",
             e if e.contains("name resolution failed") => "resolution_failed",
             e if e.contains("server misbehaving") => "server_misbehaving",
             e if e.contains("network is unreachable") => "network_unreachable",
@@ -233,19 +806,13 @@ impl DnsCollector {
                 warn!(?e, "Unable to parse DNS error, returning unknown_error");
                 "unknown_error"
             }
-        };
-        labels.insert("error_type", error_type.into());
-
-        counter!(
-            format!("{}dns_lookup_error_total", self.config.common_config.prefix),
-            &labels
-        )
-        .increment(1);
+        }
     }
 
     fn record_success_metrics(
         &self,
         result: &PerformDnsResponseResultsItemResult,
+        answered: &[LookupTypes],
         duration: f64,
         labels: &HashMap<&'static str, String>,
     ) {
@@ -254,36 +821,401 @@ impl DnsCollector {
         // Record lookup duration
         histogram!(format!("{}dns_server_lookup_duration_ms", prefix), labels).record(duration);
 
-        // Record counts and hashes based on lookup type
-        let (record_type, count, hash) = match self.config.lookup_type {
-            LookupTypes::IP => ("ip", result.ips.len(), Self::hash_records(&result.ips[..])),
-            LookupTypes::MX => ("mx", result.mx.len(), Self::hash_records(&result.mx[..])),
-            LookupTypes::TXT => ("txt", result.txt.len(), Self::hash_records(&result.txt[..])),
-            LookupTypes::NS => ("ns", result.ns.len(), Self::hash_records(&result.ns[..])),
-            LookupTypes::SRV => ("srv", result.srv.len(), Self::hash_records(&result.srv[..])),
-            LookupTypes::TLSA => (
+        // Record counts and hashes for every answered lookup type
+        for (record_type, records) in Self::answers(result, answered) {
+            let (count, hash) = (records.len(), Self::hash_records(records));
+
+            let mut record_labels = labels.clone();
+            record_labels.insert("record_type", record_type.into());
+
+            gauge!(format!("{}dns_record_hash", prefix), &record_labels).set(hash as f64);
+            gauge!(format!("{}dns_records_count", prefix), &record_labels).set(count as f64);
+
+            if count > 0 {
+                counter!(
+                    format!("{}dns_lookup_success_total", prefix),
+                    &record_labels
+                )
+                .increment(1);
+            }
+
+            counter!(format!("{}dns_lookup_total", prefix), &record_labels).increment(1);
+        }
+    }
+
+    /// Exports MX, SRV and TLSA records field by field, so dashboards show exactly which
+    /// mail exchangers and service endpoints each region gets
+    fn record_structured(
+        &self,
+        result: &PerformDnsResponseResultsItemResult,
+        labels: &HashMap<&'static str, String>,
+    ) {
+        for mx in records::parse_all::<records::Mx>(&result.mx) {
+            self.record_info(
+                "mx",
+                labels,
+                [
+                    ("preference", mx.preference.to_string()),
+                    ("exchange", mx.exchange),
+                ],
+            );
+        }
+
+        for srv in records::parse_all::<records::Srv>(&result.srv) {
+            self.record_info(
+                "srv",
+                labels,
+                [
+                    ("priority", srv.priority.to_string()),
+                    ("weight", srv.weight.to_string()),
+                    ("port", srv.port.to_string()),
+                    ("target", srv.target),
+                ],
+            );
+        }
+
+        for tlsa in records::parse_all::<records::Tlsa>(&result.tlsa) {
+            self.record_info(
                 "tlsa",
-                result.tlsa.len(),
-                Self::hash_records(&result.tlsa[..]),
+                labels,
+                [
+                    ("usage", tlsa.usage.to_string()),
+                    ("selector", tlsa.selector.to_string()),
+                    ("matching_type", tlsa.matching_type.to_string()),
+                    ("digest", tlsa.digest()),
+                ],
+            );
+        }
+    }
+
+    fn record_info<const N: usize>(
+        &self,
+        record_type: &'static str,
+        labels: &HashMap<&'static str, String>,
+        fields: [(&'static str, String); N],
+    ) {
+        let prefix = &self.config.common_config.prefix;
+
+        // Records are exported per country, so dashboards show which records each region
+        // gets, without multiplying them by every city and ISP nodes run in
+        let country_code = labels.get("country_code").cloned().unwrap_or_default();
+        let record_id = fields
+            .iter()
+            .map(|(_, value)| value.as_str())
+            .chain([country_code.as_str()])
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut info_labels = HashMap::from([
+            (
+                "endpoint",
+                labels.get("endpoint").cloned().unwrap_or_default(),
             ),
-            LookupTypes::SOA => ("soa", result.soa.len(), Self::hash_records(&result.soa[..])),
+            ("country_code", country_code),
+        ]);
+
+        if !self.record_series.admit(record_type, record_id) {
+            info_labels.insert("record_type", record_type.into());
+            counter!(
+                format!("{}dns_record_info_dropped_total", prefix),
+                &info_labels
+            )
+            .increment(1);
+            return;
+        }
+
+        info_labels.extend(fields);
+        gauge!(
+            format!("{}dns_{}_record_info", prefix, record_type),
+            &info_labels
+        )
+        .set(1.0);
+    }
+
+    /// Which networks each region's resolvers and answers land on
+    fn record_networks(
+        &self,
+        result: &PerformDnsResponseResultsItemResult,
+        labels: &HashMap<&'static str, String>,
+    ) {
+        if !asn::enabled() {
+            return;
+        }
+
+        let prefix = &self.config.common_config.prefix;
+        let networks = |addresses: &[String]| -> HashSet<asn::AsInfo> {
+            addresses
+                .iter()
+                .filter_map(|a| asn::lookup_str(a))
+                .collect()
+        };
+
+        for network in networks(&result.dns_servers) {
+            let mut asn_labels = labels.clone();
+            network.label(&mut asn_labels);
+            gauge!(format!("{}dns_resolver_asn_info", prefix), &asn_labels).set(1.0);
+        }
+
+        for network in networks(&result.ips) {
+            let mut asn_labels = labels.clone();
+            network.label(&mut asn_labels);
+            gauge!(format!("{}dns_answer_asn_info", prefix), &asn_labels).set(1.0);
+        }
+    }
+
+    /// Compares the records against the last set seen from the same location and
+    /// records what was added and removed if they changed
+    fn detect_changes(
+        &self,
+        result: &PerformDnsResponseResultsItemResult,
+        answered: &[LookupTypes],
+        labels: &HashMap<&'static str, String>,
+    ) {
+        for (record_type, records) in Self::answers(result, answered) {
+            self.detect_record_changes(record_type, records, labels);
+        }
+    }
+
+    fn detect_record_changes(
+        &self,
+        record_type: &'static str,
+        records: &[String],
+        labels: &HashMap<&'static str, String>,
+    ) {
+        let key = changes::RecordSetKey {
+            endpoint: labels
+                .get("endpoint")
+                .map(String::as_str)
+                .unwrap_or_default(),
+            record_type,
+            location: location(labels),
         };
 
+        let Some(change) = changes::observe(key, records) else {
+            return;
+        };
+
+        info!(
+            endpoint = %change.endpoint,
+            record_type,
+            location = %change.location,
+            added = ?change.added,
+            removed = ?change.removed,
+            "DNS record set changed"
+        );
+
+        let prefix = &self.config.common_config.prefix;
         let mut record_labels = labels.clone();
         record_labels.insert("record_type", record_type.into());
 
-        gauge!(format!("{}dns_record_hash", prefix), &record_labels).set(hash as f64);
-        gauge!(format!("{}dns_records_count", prefix), &record_labels).set(count as f64);
+        counter!(
+            format!("{}dns_record_changes_total", prefix),
+            &record_labels
+        )
+        .increment(1);
+    }
+
+    /// Exports the SOA serial seen from this location and how long it took to get here
+    fn track_soa_serial(
+        &self,
+        result: &PerformDnsResponseResultsItemResult,
+        labels: &HashMap<&'static str, String>,
+    ) {
+        let Some(soa) = result.soa.iter().find_map(|record| {
+            record
+                .parse::<soa::Soa>()
+                .inspect_err(|_| warn!(?record, "Unable to parse SOA record"))
+                .ok()
+        }) else {
+            return;
+        };
+
+        let prefix = &self.config.common_config.prefix;
+        let endpoint = labels.get("endpoint").cloned().unwrap_or_default();
+        let sighting = soa::observe(&endpoint, location(labels), soa.serial);
+
+        gauge!(format!("{}dns_soa_serial", prefix), labels).set(soa.serial as f64);
+        gauge!(format!("{}dns_soa_serial_highest", prefix), "endpoint" => endpoint.clone())
+            .set(sighting.highest as f64);
+
+        if let Some(lag) = sighting.propagation_lag {
+            info!(
+                endpoint,
+                serial = soa.serial,
+                country_code = labels.get("country_code"),
+                lag_secs = lag.num_seconds(),
+                "Location caught up with the latest SOA serial"
+            );
+
+            histogram!(
+                format!("{}dns_soa_propagation_lag_seconds", prefix),
+                "endpoint" => endpoint,
+                "country_code" => labels.get("country_code").cloned().unwrap_or_default()
+            )
+            .record(lag.num_milliseconds() as f64 / 1000.0);
+        }
+    }
+
+    /// Compares the answers with the ones other locations got, when a consensus is configured
+    fn check_consensus(
+        &self,
+        result: &PerformDnsResponseResultsItemResult,
+        answered: &[LookupTypes],
+        labels: &HashMap<&'static str, String>,
+    ) {
+        let Some(config) = &self.config.consensus else {
+            return;
+        };
+
+        let prefix = &self.config.common_config.prefix;
+        let label = |name: &str| labels.get(name).cloned().unwrap_or_default();
+
+        let mut dns_servers: Vec<String> = dns_server_providers(result).into_iter().collect();
+        dns_servers.sort();
+
+        for (record_type, records) in Self::answers(result, answered) {
+            let answer = consensus::Answer {
+                endpoint: labels
+                    .get("endpoint")
+                    .map(String::as_str)
+                    .unwrap_or_default(),
+                record_type,
+                location: location(labels),
+                country_code: label("country_code"),
+                isp: label("isp"),
+                dns_servers: dns_servers.clone(),
+                records,
+            };
+            let suspicious = consensus::observe(answer, config);
+
+            let mut record_labels = labels.clone();
+            record_labels.insert("record_type", record_type.into());
+            gauge!(format!("{}dns_answer_deviation", prefix), &record_labels)
+                .set(suspicious.is_some() as u8 as f64);
+
+            if let Some(suspicious) = suspicious {
+                warn!(
+                    endpoint = %suspicious.endpoint,
+                    record_type,
+                    country_code = %suspicious.country_code,
+                    isp = %suspicious.isp,
+                    dns_servers = ?suspicious.dns_servers,
+                    deviations = ?suspicious.deviations,
+                    "DNS answer deviates from the other locations"
+                );
+            }
+        }
+    }
+
+    /// Compares the records of every lookup type with an expected answer configured.
+    /// Returns whether they all matched.
+    fn check_expected_answer(
+        &self,
+        result: &PerformDnsResponseResultsItemResult,
+        answered: &[LookupTypes],
+        labels: &HashMap<&'static str, String>,
+    ) -> bool {
+        let mut all_match = true;
+
+        for lookup_type in answered {
+            if let Some(expected) = self.config.expect.get(lookup_type) {
+                let (record_type, records) = Self::records(result, *lookup_type);
+                all_match &= self.check_records(expected, record_type, records, labels);
+            }
+        }
+
+        all_match
+    }
+
+    fn check_records(
+        &self,
+        expected: &ExpectedAnswer,
+        record_type: &'static str,
+        records: &[String],
+        labels: &HashMap<&'static str, String>,
+    ) -> bool {
+        let prefix = &self.config.common_config.prefix;
+        let unexpected = unexpected_records(expected, records);
+        let matches = unexpected.is_ok();
+
+        let mut record_labels = labels.clone();
+        record_labels.insert("record_type", record_type.into());
+
+        gauge!(format!("{}dns_answer_match", prefix), &record_labels).set(matches as u8 as f64);
+
+        if let Err(unexpected) = unexpected {
+            warn!(
+                endpoint = %self.config.common_config.endpoint,
+                record_type,
+                country_code = labels.get("country_code"),
+                isp = labels.get("isp"),
+                ?records,
+                ?unexpected,
+                "DNS answer does not match the expected records"
+            );
 
-        if count > 0 {
             counter!(
-                format!("{}dns_lookup_success_total", prefix),
+                format!("{}dns_answer_mismatch_total", prefix),
                 &record_labels
             )
             .increment(1);
         }
 
-        counter!(format!("{}dns_lookup_total", prefix), &record_labels).increment(1);
+        matches
+    }
+
+    /// Record type label and records of every configured lookup type
+    fn lookups<'a>(
+        &'a self,
+        result: &'a PerformDnsResponseResultsItemResult,
+    ) -> impl Iterator<Item = (&'static str, &'a [String])> {
+        Self::answers(result, &self.lookup_types)
+    }
+
+    /// Lookup types with an answer to export. Without an error that is every type, empty or
+    /// not. With several lookup types the API returns the records it found next to one error
+    /// for the types that had none, so only the types that returned records are left, and
+    /// the error series cover the others.
+    fn answered_lookups(
+        lookup_types: &[LookupTypes],
+        item: &PerformDnsResponseResultsItem,
+    ) -> Vec<LookupTypes> {
+        let Some(result) = &item.result else {
+            return Vec::new();
+        };
+
+        lookup_types
+            .iter()
+            .copied()
+            .filter(|lookup_type| {
+                item.error.is_none() || !Self::records(result, *lookup_type).1.is_empty()
+            })
+            .collect()
+    }
+
+    fn answers<'a>(
+        result: &'a PerformDnsResponseResultsItemResult,
+        lookup_types: &'a [LookupTypes],
+    ) -> impl Iterator<Item = (&'static str, &'a [String])> {
+        lookup_types
+            .iter()
+            .map(|lookup_type| Self::records(result, *lookup_type))
+    }
+
+    /// Returns the record type label and the records answering a lookup type
+    fn records(
+        result: &PerformDnsResponseResultsItemResult,
+        lookup_type: LookupTypes,
+    ) -> (&'static str, &[String]) {
+        match lookup_type {
+            LookupTypes::IP => ("ip", &result.ips),
+            LookupTypes::MX => ("mx", &result.mx),
+            LookupTypes::TXT => ("txt", &result.txt),
+            LookupTypes::NS => ("ns", &result.ns),
+            LookupTypes::SRV => ("srv", &result.srv),
+            LookupTypes::TLSA => ("tlsa", &result.tlsa),
+            LookupTypes::SOA => ("soa", &result.soa),
+        }
     }
 
     fn hash_records<T: AsRef<str>>(records: &[T]) -> u64 {
@@ -298,13 +1230,151 @@ impl DnsCollector {
     }
 }
 
+/// Random DNS label, unique enough that no resolver has it cached
+fn random_label() -> String {
+    let hash = std::collections::hash_map::RandomState::new().hash_one(SystemTime::now());
+
+    format!("{:016x}", hash)
+}
+
+/// Vantage point a probe ran from, as far as DNS answers can differ between them
+fn location(labels: &HashMap<&'static str, String>) -> String {
+    let label = |name: &str| labels.get(name).map(String::as_str).unwrap_or_default();
+
+    format!(
+        "{}/{}/{}",
+        label("country_code"),
+        label("geohash"),
+        label("isp")
+    )
+}
+
+/// Checks records against an expected answer, returning the records that are not allowed.
+/// A missing record of an exact answer is reported as an empty list of unexpected ones.
+fn unexpected_records(expected: &ExpectedAnswer, records: &[String]) -> Result<(), Vec<String>> {
+    let normalize = |r: &String| r.trim_end_matches('.').to_lowercase();
+
+    if let Some(exact) = &expected.exact {
+        let expected: HashSet<String> = exact.iter().map(normalize).collect();
+        let actual: HashSet<String> = records.iter().map(normalize).collect();
+
+        if expected != actual {
+            return Err(actual.difference(&expected).cloned().collect());
+        }
+    }
+
+    if expected.cidrs.is_empty() && expected.patterns.is_empty() {
+        return Ok(());
+    }
+
+    let unexpected: Vec<String> = records
+        .iter()
+        .filter(|record| {
+            let in_cidr = IpAddr::from_str(record)
+                .is_ok_and(|ip| expected.cidrs.iter().any(|net| net.contains(&ip)));
+            let matches_pattern = expected.patterns.iter().any(|p| p.is_match(record));
+            !(in_cidr || matches_pattern)
+        })
+        .cloned()
+        .collect();
+
+    if unexpected.is_empty() && !records.is_empty() {
+        Ok(())
+    } else {
+        Err(unexpected)
+    }
+}
+
+/// Address of a resolver as given in `dns_servers` or reported by the API: an IP or socket
+/// address such as `[2620:119:53::53]:53`, optionally behind a `udp:`/`tcp:` scheme
+pub fn server_ip(server: &str) -> Option<IpAddr> {
+    let parse = |address: &str| {
+        IpAddr::from_str(address)
+            .or_else(|_| SocketAddr::from_str(address).map(|a| a.ip()))
+            .ok()
+    };
+
+    // The scheme is only stripped once the whole string failed to parse, trimming first
+    // would also eat into IPv6 addresses
+    parse(server).or_else(|| {
+        let address = server
+            .strip_prefix("udp:")
+            .or_else(|| server.strip_prefix("tcp:"))?;
+        parse(address)
+    })
+}
+
+/// Expands provider presets into their IPv4 resolvers from the provider catalog, since
+/// not every node has IPv6, and keeps addresses as given. Anything else is an error, which
+/// is checked on boot.
+pub fn resolve_dns_servers(servers: &[String]) -> Result<Vec<String>> {
+    let mut resolved = Vec::new();
+
+    for server in servers {
+        if server_ip(server).is_some() {
+            resolved.push(server.clone());
+            continue;
+        }
+
+        let preset: Vec<String> = providers::resolvers(server)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(IpAddr::is_ipv4)
+            .map(|ip| ip.to_string())
+            .collect();
+
+        if preset.is_empty() {
+            return Err(eyre!(
+                "Unknown DNS server or preset {:?}, expected an address or a provider from the catalog",
+                server
+            ));
+        }
+
+        resolved.extend(preset);
+    }
+
+    Ok(resolved)
+}
+
+/// Records of one type from a lookup, empty when the name has none and `None` when they
+/// could not be looked up. The API answers every lookup type of a name at once with the
+/// errors of all of them in one message, so only a `NoRecord` for this type means absence.
+fn answered_records<'a, T>(
+    item: &'a PerformDnsResponseResultsItem,
+    record_type: DnsRecordType,
+    records: impl Fn(&'a PerformDnsResponseResultsItemResult) -> &'a [T],
+) -> Option<&'a [T]> {
+    let found = item.result.as_ref().map_or(&[][..], records);
+
+    match &item.error {
+        None => Some(found),
+        Some(_) if !found.is_empty() => Some(found),
+        Some(error) => DnsErrorParser::parse(error)
+            .contains(&DnsErrorType::NoRecord(record_type))
+            .then_some(found),
+    }
+}
+
+/// Public resolvers outside every range of the provider catalog, typically the ISP's own
+const UNKNOWN_PROVIDER: &str = "Unknown Public DNS";
+
+/// Providers of the resolvers the node used
+fn dns_server_providers(result: &PerformDnsResponseResultsItemResult) -> HashSet<String> {
+    // Unparseable entries are passed on as is to be reported
+    let cleaned_dns_ips = result
+        .dns_servers
+        .iter()
+        .map(|server| server_ip(server).map_or_else(|| server.clone(), |ip| ip.to_string()));
+
+    identify_dns_providers(cleaned_dns_ips)
+}
+
 fn identify_dns_providers<I>(ips: I) -> HashSet<String>
 where
     I: IntoIterator,
     I::Item: AsRef<str>,
 {
-    let mut providers = HashSet::new();
-    let provider_map = build_provider_map();
+    let mut found = HashSet::new();
 
     for ip in ips {
         let ip_str = ip.as_ref();
@@ -326,10 +1396,8 @@ where
                     classify_private_network(&ipv4)
                 } else if is_tailscale_range(&ipv4) {
                     "Tailscale".to_string()
-                } else if let Some(provider) = provider_map.get(ip_str) {
-                    provider.to_string()
                 } else {
-                    classify_public_dns(&ipv4)
+                    providers::provider(ip_addr).unwrap_or_else(|| UNKNOWN_PROVIDER.to_string())
                 }
             }
             IpAddr::V6(ipv6) => {
@@ -337,18 +1405,16 @@ where
                     "Localhost".to_string()
                 } else if is_private_ipv6(&ipv6) {
                     classify_private_ipv6(&ipv6)
-                } else if let Some(provider) = provider_map.get(ip_str) {
-                    provider.to_string()
                 } else {
-                    classify_public_ipv6_dns(&ipv6)
+                    providers::provider(ip_addr).unwrap_or_else(|| UNKNOWN_PROVIDER.to_string())
                 }
             }
         };
 
-        providers.insert(provider);
+        found.insert(provider);
     }
 
-    providers
+    found
 }
 
 fn is_loopback_v4(ip: &Ipv4Addr) -> bool {
@@ -379,93 +1445,51 @@ fn is_private_ipv6(ip: &Ipv6Addr) -> bool {
     (segments[0] & 0xffc0) == 0xfe80
 }
 
-fn is_tailscale_range(ip: &Ipv4Addr) -> bool {
-    let octets = ip.octets();
-    octets[0] == 100 && (64..=127).contains(&octets[1])
+/// Addresses that only make sense inside a network, never as a public answer
+fn is_private_address(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_loopback_v4(ip) || is_private_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_loopback_v4(&ip) || is_private_ipv4(&ip),
+            None => is_loopback_v6(ip) || is_private_ipv6(ip),
+        },
+    }
 }
 
-fn build_provider_map() -> HashMap<String, String> {
-    let mut map = HashMap::new();
-
-    // Google DNS
-    for ip in [
-        "8.8.8.8",
-        "8.8.4.4",
-        "2001:4860:4860::8888",
-        "2001:4860:4860::8844",
-    ]
-    .iter()
-    {
-        map.insert((*ip).to_string(), "Google".to_string());
-    }
-
-    // Cloudflare DNS
-    for ip in [
-        "1.1.1.1",
-        "1.0.0.1",
-        "1.1.1.2",
-        "1.0.0.2",
-        "2606:4700:4700::1111",
-        "2606:4700:4700::1001",
-        "2606:4700:4700::1112",
-        "2606:4700:4700::1002",
-    ]
-    .iter()
-    {
-        map.insert((*ip).to_string(), "Cloudflare".to_string());
-    }
-
-    // Quad9
-    for ip in ["9.9.9.9", "149.112.112.112", "2620:fe::fe", "2620:fe::9"].iter() {
-        map.insert((*ip).to_string(), "Quad9".to_string());
-    }
-
-    // OpenDNS
-    for ip in [
-        "208.67.222.222",
-        "208.67.220.220",
-        "2620:119:35::35",
-        "2620:119:53::53",
-    ]
-    .iter()
-    {
-        map.insert((*ip).to_string(), "OpenDNS".to_string());
-    }
-
-    // AdGuard
-    for ip in [
-        "94.140.14.14",
-        "94.140.15.15",
-        "2a10:50c0::ad1:ff",
-        "2a10:50c0::ad2:ff",
-    ]
-    .iter()
-    {
-        map.insert((*ip).to_string(), "AdGuard".to_string());
-    }
-
-    // NextDNS (including all Anycast ranges)
-    let nextdns_ranges = (0..=255)
-        .filter(|&n| {
-            matches!(
-                n,
-                0 | 1 | 11 | 42 | 68 | 99 | 139 | 165 | 185 | 216 | 233 | 241
-            )
-        })
-        .flat_map(|n| {
-            vec![
-                format!("45.90.28.{}", n),
-                format!("45.90.30.{}", n),
-                format!("2a07:a8c0::{:x}", n),
-                format!("2a07:a8c1::{:x}", n),
-            ]
-        });
-
-    for ip in nextdns_ranges {
-        map.insert(ip, "NextDNS".to_string());
+/// Reserved, documentation and other ranges that are never routed on the internet
+fn is_bogon(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || octets[0] == 0
+                || octets[0] >= 240
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (octets[0] == 100 && (64..=127).contains(&octets[1]))
+                // IETF protocol assignments, 192.0.0.0/24
+                || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0)
+                // Benchmarking, 198.18.0.0/15
+                || (octets[0] == 198 && (18..=19).contains(&octets[1]))
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            ip.is_unspecified()
+                || ip.is_multicast()
+                // Documentation, 2001:db8::/32
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+                // Discard-only, 100::/64
+                || (segments[0] == 0x0100 && segments[1..4] == [0, 0, 0])
+                || ip.to_ipv4_mapped().is_some_and(|ip| is_bogon(&IpAddr::V4(ip)))
+        }
     }
+}
 
-    map
+fn is_tailscale_range(ip: &Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    octets[0] == 100 && (64..=127).contains(&octets[1])
 }
 
 fn classify_private_network(ip: &Ipv4Addr) -> String {
@@ -490,65 +1514,110 @@ fn classify_private_ipv6(ip: &Ipv6Addr) -> String {
     }
 }
 
-fn classify_public_dns(ip: &Ipv4Addr) -> String {
-    let octets = ip.octets();
-
-    match octets[0] {
-        // Reserved for IANA special use
-        0 | 127 | 169 | 192 | 198 => "Reserved Range".to_string(),
-
-        // Major ISP ranges
-        1..=100 => {
-            if is_known_isp_range(ip) {
-                "ISP DNS".to_string()
-            } else {
-                "Unknown Public DNS".to_string()
-            }
-        }
-
-        // Enterprise ranges
-        128..=191 => "Enterprise DNS".to_string(),
-
-        _ => "Unknown Public DNS".to_string(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_server_addresses() {
+        let ip = |s: &str| Some(IpAddr::from_str(s).unwrap());
+
+        assert_eq!(server_ip("9.9.9.9"), ip("9.9.9.9"));
+        assert_eq!(server_ip("udp:9.9.9.9:53"), ip("9.9.9.9"));
+        assert_eq!(server_ip("tcp:9.9.9.9"), ip("9.9.9.9"));
+        assert_eq!(server_ip("2620:119:53::53"), ip("2620:119:53::53"));
+        assert_eq!(server_ip("udp:2620:119:53::53"), ip("2620:119:53::53"));
+        assert_eq!(server_ip("[2620:119:53::53]:53"), ip("2620:119:53::53"));
+        assert_eq!(
+            server_ip("tcp:[2620:119:53::53]:853"),
+            ip("2620:119:53::53")
+        );
+        assert_eq!(server_ip("cloudflare"), None);
+        assert_eq!(server_ip("udp:9.9.9.9:dns"), None);
     }
-}
-
-fn classify_public_ipv6_dns(ip: &Ipv6Addr) -> String {
-    let segments = ip.segments();
 
-    match segments[0] {
-        // Global Unicast Address (2000::/3)
-        s if (0x2000..=0x3FFF).contains(&s) => {
-            if is_known_ipv6_dns_range(ip) {
-                "Known IPv6 DNS Provider".to_string()
-            } else {
-                "ISP IPv6 DNS".to_string()
-            }
-        }
+    #[test]
+    fn only_no_record_errors_mean_absence() {
+        let item = |value: serde_json::Value| -> PerformDnsResponseResultsItem {
+            serde_json::from_value(value).unwrap()
+        };
+        let result = serde_json::json!({
+            "mx": [], "ns": [], "ips": [], "txt": ["v=spf1 -all"], "soa": [],
+            "srv": [], "tlsa": [], "dnsServers": []
+        });
+        let txt = |item: &PerformDnsResponseResultsItem| {
+            answered_records(item, DnsRecordType::TXT, |r| r.txt.as_slice()).map(<[_]>::len)
+        };
+        let no_record = |query_type: &str| {
+            format!("no record found for Query {{ name: Name(\"_dmarc.example.com.\"), query_type: {}, query_class: IN }}", query_type)
+        };
 
-        _ => "Unknown IPv6 DNS".to_string(),
+        // Records found next to an error for another lookup type
+        let found = item(serde_json::json!({
+            "endpoint": "example.com",
+            "error": no_record("MX"),
+            "result": result,
+        }));
+        assert_eq!(txt(&found), Some(1));
+
+        let absent = item(serde_json::json!({
+            "endpoint": "_dmarc.example.com",
+            "error": format!("{}\n{}", no_record("MX"), no_record("TXT")),
+        }));
+        assert_eq!(txt(&absent), Some(0));
+
+        let only_mx = item(serde_json::json!({
+            "endpoint": "_dmarc.example.com",
+            "error": no_record("MX"),
+        }));
+        assert_eq!(txt(&only_mx), None);
+
+        let timeout = item(serde_json::json!({
+            "endpoint": "_dmarc.example.com",
+            "error": "request timed out",
+        }));
+        assert_eq!(txt(&timeout), None);
     }
-}
 
-fn is_known_isp_range(ip: &Ipv4Addr) -> bool {
-    let octets = ip.octets();
-
-    matches!(
-        (octets[0], octets[1]),
-        (24..=50, _) |    // ARIN space
-        (62..=70, _) |    // RIPE space
-        (80..=90, _) |    // RIPE space
-        (98..=100, _) // APNIC space
-    )
-}
+    #[test]
+    fn exports_the_lookup_types_that_returned_records() {
+        let lookup_types = [LookupTypes::IP, LookupTypes::MX, LookupTypes::TXT];
+        let item = |error: Option<&str>| -> PerformDnsResponseResultsItem {
+            serde_json::from_value(serde_json::json!({
+                "endpoint": "example.com",
+                "error": error,
+                "result": {
+                    "mx": [], "ns": [], "ips": ["192.0.2.1"], "txt": ["v=spf1 -all"],
+                    "soa": [], "srv": [], "tlsa": [], "dnsServers": []
+                },
+            }))
+            .unwrap()
+        };
 
-fn is_known_ipv6_dns_range(ip: &Ipv6Addr) -> bool {
-    let segments = ip.segments();
+        assert_eq!(
+            DnsCollector::answered_lookups(&lookup_types, &item(None)),
+            lookup_types
+        );
+        assert_eq!(
+            DnsCollector::answered_lookups(
+                &lookup_types,
+                &item(Some(
+                    "no record found for Query { query_type: MX, query_class: IN }"
+                ))
+            ),
+            [LookupTypes::IP, LookupTypes::TXT]
+        );
+    }
 
-    matches!(
-        segments[0],
-        0x2001 |  // Teredo
-        0x2606 |  // Various providers
-        0x2620 // Various providers
-    )
+    #[test]
+    fn rejects_unknown_dns_servers() {
+        assert_eq!(
+            resolve_dns_servers(&["udp:9.9.9.9:53".to_string()]).unwrap(),
+            ["udp:9.9.9.9:53"]
+        );
+        assert!(!resolve_dns_servers(&["cloudflare".to_string()])
+            .unwrap()
+            .is_empty());
+        assert!(resolve_dns_servers(&["not-a-resolver".to_string()]).is_err());
+    }
 }
@@ -0,0 +1,299 @@
+use crate::config::ProviderCatalogConfig;
+use color_eyre::eyre::{Result, WrapErr};
+use figment::providers::{Format, Yaml};
+use figment::Figment;
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::fs;
+use std::net::IpAddr;
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::SystemTime;
+use tokio::sync::watch;
+use tracing::{error, info};
+
+/// Catalog shipped with the binary, covering the major public resolvers
+const BUNDLED: &str = include_str!("providers.yaml");
+
+static CATALOG: LazyLock<RwLock<Arc<Catalog>>> = LazyLock::new(|| {
+    let bundled = parse(BUNDLED).expect("bundled provider catalog is valid");
+    RwLock::new(Arc::new(Catalog::build(&bundled)))
+});
+
+#[derive(Deserialize)]
+struct CatalogFile {
+    providers: Vec<ProviderEntry>,
+}
+
+#[derive(Deserialize, Clone)]
+struct ProviderEntry {
+    name: String,
+    /// Addresses `dns_servers` presets expand to
+    #[serde(default)]
+    resolvers: Vec<IpAddr>,
+    #[serde(default)]
+    ranges: Vec<IpNet>,
+}
+
+/// Binary trie over address bits, the deepest prefix holding a provider wins
+#[derive(Default)]
+struct PrefixTrie {
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Default)]
+struct TrieNode {
+    children: [Option<usize>; 2],
+    provider: Option<usize>,
+}
+
+impl PrefixTrie {
+    fn bit(address: u128, width: u8, index: u8) -> usize {
+        ((address >> (width - 1 - index)) & 1) as usize
+    }
+
+    fn insert(&mut self, address: u128, width: u8, prefix_len: u8, provider: usize) {
+        if self.nodes.is_empty() {
+            self.nodes.push(TrieNode::default());
+        }
+
+        let mut node = 0;
+        for index in 0..prefix_len {
+            let bit = Self::bit(address, width, index);
+            node = match self.nodes[node].children[bit] {
+                Some(child) => child,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[bit] = Some(child);
+                    child
+                }
+            };
+        }
+
+        self.nodes[node].provider = Some(provider);
+    }
+
+    fn longest_match(&self, address: u128, width: u8) -> Option<usize> {
+        let mut node = self.nodes.first()?;
+        let mut found = node.provider;
+
+        for index in 0..width {
+            let Some(child) = node.children[Self::bit(address, width, index)] else {
+                break;
+            };
+            node = &self.nodes[child];
+            found = node.provider.or(found);
+        }
+
+        found
+    }
+}
+
+struct Catalog {
+    providers: Vec<ProviderEntry>,
+    v4: PrefixTrie,
+    v6: PrefixTrie,
+}
+
+impl Catalog {
+    /// Later entries take precedence over earlier ones for the same range
+    fn build(entries: &[ProviderEntry]) -> Self {
+        let mut catalog = Catalog {
+            providers: entries.to_vec(),
+            v4: PrefixTrie::default(),
+            v6: PrefixTrie::default(),
+        };
+
+        for (index, entry) in entries.iter().enumerate() {
+            for range in &entry.ranges {
+                match range.trunc() {
+                    IpNet::V4(net) => catalog.v4.insert(
+                        u32::from(net.network()) as u128,
+                        32,
+                        net.prefix_len(),
+                        index,
+                    ),
+                    IpNet::V6(net) => {
+                        catalog
+                            .v6
+                            .insert(u128::from(net.network()), 128, net.prefix_len(), index)
+                    }
+                }
+            }
+        }
+
+        catalog
+    }
+
+    fn lookup(&self, ip: IpAddr) -> Option<&str> {
+        let index = match ip {
+            IpAddr::V4(ip) => self.v4.longest_match(u32::from(ip) as u128, 32),
+            IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => self.v4.longest_match(u32::from(ip) as u128, 32),
+                None => self.v6.longest_match(u128::from(ip), 128),
+            },
+        }?;
+
+        Some(&self.providers[index].name)
+    }
+}
+
+fn parse(contents: &str) -> Result<Vec<ProviderEntry>> {
+    let file: CatalogFile = Figment::from(Yaml::string(contents)).extract()?;
+    Ok(file.providers)
+}
+
+/// Name of the provider whose range contains the address
+pub fn provider(ip: IpAddr) -> Option<String> {
+    CATALOG.read().unwrap().lookup(ip).map(str::to_string)
+}
+
+/// Resolver addresses of the provider with the given name, compared case-insensitively
+pub fn resolvers(name: &str) -> Option<Vec<IpAddr>> {
+    let catalog = CATALOG.read().unwrap().clone();
+
+    catalog
+        .providers
+        .iter()
+        .rev()
+        .find(|entry| entry.name.eq_ignore_ascii_case(name) && !entry.resolvers.is_empty())
+        .map(|entry| entry.resolvers.clone())
+}
+
+/// Replaces the catalog with the configured file, on top of the bundled one unless disabled.
+/// JSON files are read as well, being valid YAML.
+pub fn load(config: &ProviderCatalogConfig) -> Result<()> {
+    let contents = fs::read_to_string(&config.path)
+        .wrap_err_with(|| format!("Unable to read provider catalog {:?}", config.path))?;
+    let from_file =
+        parse(&contents).wrap_err_with(|| format!("Invalid provider catalog {:?}", config.path))?;
+
+    let mut entries = if config.include_bundled {
+        parse(BUNDLED)?
+    } else {
+        Vec::new()
+    };
+    entries.extend(from_file);
+
+    let catalog = Catalog::build(&entries);
+    info!(
+        path = ?config.path,
+        providers = catalog.providers.len(),
+        "Loaded DNS provider catalog"
+    );
+    *CATALOG.write().unwrap() = Arc::new(catalog);
+
+    Ok(())
+}
+
+fn modified(config: &ProviderCatalogConfig) -> Option<SystemTime> {
+    fs::metadata(&config.path).and_then(|m| m.modified()).ok()
+}
+
+/// Reloads the catalog whenever the file changes. A file that fails to load leaves the
+/// previous catalog in place.
+pub async fn run(config: &'static ProviderCatalogConfig, mut shutdown: watch::Receiver<bool>) {
+    let mut last_modified = modified(config);
+    let mut interval = tokio::time::interval(config.reload_interval);
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let current = modified(config);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+
+                if let Err(e) = load(config) {
+                    error!(?e, "Failed to reload DNS provider catalog, keeping the previous one");
+                }
+            }
+            _ = shutdown.changed() => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v4(address: &str) -> u128 {
+        u32::from(address.parse::<std::net::Ipv4Addr>().unwrap()) as u128
+    }
+
+    fn entry(name: &str, ranges: &[&str]) -> ProviderEntry {
+        ProviderEntry {
+            name: name.to_string(),
+            resolvers: Vec::new(),
+            ranges: ranges.iter().map(|r| r.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let mut trie = PrefixTrie::default();
+        trie.insert(v4("10.0.0.0"), 32, 8, 0);
+        trie.insert(v4("10.1.0.0"), 32, 16, 1);
+        trie.insert(v4("10.1.2.3"), 32, 32, 2);
+
+        assert_eq!(trie.longest_match(v4("10.9.9.9"), 32), Some(0));
+        assert_eq!(trie.longest_match(v4("10.1.9.9"), 32), Some(1));
+        assert_eq!(trie.longest_match(v4("10.1.2.3"), 32), Some(2));
+        assert_eq!(trie.longest_match(v4("10.1.2.4"), 32), Some(1));
+        assert_eq!(trie.longest_match(v4("11.0.0.0"), 32), None);
+    }
+
+    #[test]
+    fn empty_trie_matches_nothing() {
+        assert_eq!(PrefixTrie::default().longest_match(v4("1.1.1.1"), 32), None);
+    }
+
+    #[test]
+    fn zero_length_prefix_matches_everything() {
+        let mut trie = PrefixTrie::default();
+        trie.insert(0, 32, 0, 0);
+        trie.insert(v4("192.168.0.0"), 32, 16, 1);
+
+        assert_eq!(trie.longest_match(v4("8.8.8.8"), 32), Some(0));
+        assert_eq!(trie.longest_match(v4("192.168.1.1"), 32), Some(1));
+    }
+
+    #[test]
+    fn later_entries_replace_the_same_range() {
+        let mut trie = PrefixTrie::default();
+        trie.insert(v4("1.1.1.0"), 32, 24, 0);
+        trie.insert(v4("1.1.1.0"), 32, 24, 1);
+
+        assert_eq!(trie.longest_match(v4("1.1.1.1"), 32), Some(1));
+    }
+
+    #[test]
+    fn looks_up_both_address_families() {
+        let catalog = Catalog::build(&[
+            entry("Cloudflare", &["1.1.1.0/24", "2606:4700:4700::/48"]),
+            entry("Local", &["1.1.1.128/25"]),
+        ]);
+        let lookup = |ip: &str| catalog.lookup(ip.parse().unwrap());
+
+        assert_eq!(lookup("1.1.1.1"), Some("Cloudflare"));
+        assert_eq!(lookup("1.1.1.200"), Some("Local"));
+        assert_eq!(lookup("2606:4700:4700::1111"), Some("Cloudflare"));
+        // IPv4-mapped addresses are looked up as IPv4
+        assert_eq!(lookup("::ffff:1.1.1.1"), Some("Cloudflare"));
+        assert_eq!(lookup("2001:db8::1"), None);
+        assert_eq!(lookup("8.8.8.8"), None);
+    }
+
+    #[test]
+    fn bundled_catalog_parses() {
+        let catalog = Catalog::build(&parse(BUNDLED).unwrap());
+
+        assert_eq!(catalog.lookup("8.8.8.8".parse().unwrap()), Some("Google"));
+        assert!(catalog
+            .providers
+            .iter()
+            .any(|p| p.name == "Cloudflare" && !p.resolvers.is_empty()));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::relabel::fnv1a;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Some resolvers prefix records with the owner, TTL, class and type, so fields are
+/// taken from after the type when it is present
+fn rdata<'a>(record: &'a str, record_type: &str) -> Vec<&'a str> {
+    let fields: Vec<&str> = record.split_whitespace().collect();
+
+    match fields
+        .iter()
+        .position(|f| f.eq_ignore_ascii_case(record_type))
+    {
+        Some(index) => fields[index + 1..].to_vec(),
+        None => fields,
+    }
+}
+
+fn number<T: FromStr>(field: &str) -> Result<T, ()> {
+    field.parse().map_err(|_| ())
+}
+
+/// A parsed MX record, e.g. `10 mail.example.com.`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mx {
+    pub preference: u16,
+    pub exchange: String,
+}
+
+impl FromStr for Mx {
+    type Err = ();
+
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let [preference, exchange] = rdata(record, "MX")[..] else {
+            return Err(());
+        };
+
+        Ok(Mx {
+            preference: number(preference)?,
+            exchange: exchange.trim_end_matches('.').to_lowercase(),
+        })
+    }
+}
+
+/// A parsed SRV record, e.g. `10 60 5060 sip.example.com.`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Srv {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+impl FromStr for Srv {
+    type Err = ();
+
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let [priority, weight, port, target] = rdata(record, "SRV")[..] else {
+            return Err(());
+        };
+
+        Ok(Srv {
+            priority: number(priority)?,
+            weight: number(weight)?,
+            port: number(port)?,
+            target: target.trim_end_matches('.').to_lowercase(),
+        })
+    }
+}
+
+/// A parsed TLSA record, e.g. `3 1 1 0b9fa5a59eed715c26c1020c711b4f6ec42d58b0015e14337a39dad301c5afc3`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tlsa {
+    pub usage: u8,
+    pub selector: u8,
+    pub matching_type: u8,
+    /// Certificate association data as lowercase hex, split records are joined
+    pub data: String,
+}
+
+impl Tlsa {
+    /// Short, stable digest of the association data, which can be a whole certificate
+    pub fn digest(&self) -> String {
+        format!("{:016x}", fnv1a(self.data.as_bytes()))
+    }
+}
+
+impl FromStr for Tlsa {
+    type Err = ();
+
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let [usage, selector, matching_type, ref data @ ..] = rdata(record, "TLSA")[..] else {
+            return Err(());
+        };
+
+        let data = data.concat().to_lowercase();
+        if data.is_empty() || !data.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(());
+        }
+
+        Ok(Tlsa {
+            usage: number(usage)?,
+            selector: number(selector)?,
+            matching_type: number(matching_type)?,
+            data,
+        })
+    }
+}
+
+/// Records that parse, the others are skipped
+pub fn parse_all<T: FromStr>(records: &[String]) -> impl Iterator<Item = T> + '_ {
+    records.iter().filter_map(|r| r.parse().ok())
+}
+
+/// Bounds the number of info series a check exports per record type, one for each record
+/// and region it was seen in. Series first seen once the cap is reached are left out. Records not seen within `idle`
+/// are dropped by the exporter as well, so they give their place back.
+pub struct RecordSeriesCap {
+    max: usize,
+    idle: Duration,
+    seen: Mutex<HashMap<&'static str, HashMap<String, Instant>>>,
+}
+
+impl RecordSeriesCap {
+    pub fn new(max: usize, idle: Duration) -> Self {
+        Self {
+            max,
+            idle,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the series was admitted before or still fits under the cap
+    pub fn admit(&self, record_type: &'static str, record: String) -> bool {
+        self.admit_at(record_type, record, Instant::now())
+    }
+
+    fn admit_at(&self, record_type: &'static str, record: String, now: Instant) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        let records = seen.entry(record_type).or_default();
+
+        if let Some(last_seen) = records.get_mut(&record) {
+            *last_seen = now;
+            return true;
+        }
+
+        if records.len() >= self.max {
+            records.retain(|_, last_seen| now.duration_since(*last_seen) < self.idle);
+        }
+        records.len() < self.max && records.insert(record, now).is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mx_records() {
+        let expected = Mx {
+            preference: 10,
+            exchange: "mail.example.com".into(),
+        };
+        assert_eq!("10 mail.example.com.".parse(), Ok(expected.clone()));
+        assert_eq!(
+            "example.com. 300 IN MX 10 Mail.Example.com.".parse(),
+            Ok(expected)
+        );
+
+        assert_eq!("mail.example.com.".parse::<Mx>(), Err(()));
+        assert_eq!("high mail.example.com.".parse::<Mx>(), Err(()));
+        assert_eq!("70000 mail.example.com.".parse::<Mx>(), Err(()));
+        assert_eq!("10 mail.example.com. extra".parse::<Mx>(), Err(()));
+    }
+
+    #[test]
+    fn parses_srv_records() {
+        let expected = Srv {
+            priority: 10,
+            weight: 60,
+            port: 5060,
+            target: "sip.example.com".into(),
+        };
+        assert_eq!("10 60 5060 sip.example.com.".parse(), Ok(expected.clone()));
+        assert_eq!(
+            "_sip._tcp.example.com. 86400 IN SRV 10 60 5060 sip.example.com.".parse(),
+            Ok(expected)
+        );
+
+        assert_eq!("10 60 sip.example.com.".parse::<Srv>(), Err(()));
+        assert_eq!("10 60 sip 5060".parse::<Srv>(), Err(()));
+    }
+
+    #[test]
+    fn parses_tlsa_records() {
+        let data = "0b9fa5a59eed715c26c1020c711b4f6ec42d58b0015e14337a39dad301c5afc3";
+        let expected = Tlsa {
+            usage: 3,
+            selector: 1,
+            matching_type: 1,
+            data: data.into(),
+        };
+        assert_eq!(format!("3 1 1 {}", data).parse(), Ok(expected.clone()));
+        assert_eq!(
+            format!("_443._tcp.example.com. 3600 IN TLSA 3 1 1 {}", data).parse(),
+            Ok(expected.clone())
+        );
+        // Long association data comes split and in either case
+        assert_eq!(
+            format!("3 1 1 {} {}", &data[..32], data[32..].to_uppercase()).parse(),
+            Ok(expected.clone())
+        );
+        assert_eq!(expected.digest().len(), 16);
+
+        assert_eq!("3 1 1".parse::<Tlsa>(), Err(()));
+        assert_eq!("3 1 1 not-hex".parse::<Tlsa>(), Err(()));
+        assert_eq!(format!("3 x 1 {}", data).parse::<Tlsa>(), Err(()));
+    }
+
+    #[test]
+    fn parse_all_skips_malformed_records() {
+        let records = vec![
+            "10 mx1.example.com.".to_string(),
+            "garbage".to_string(),
+            "20 mx2.example.com.".to_string(),
+        ];
+        let exchanges: Vec<String> = parse_all::<Mx>(&records).map(|mx| mx.exchange).collect();
+        assert_eq!(exchanges, ["mx1.example.com", "mx2.example.com"]);
+    }
+
+    #[test]
+    fn caps_records_until_they_go_idle() {
+        let cap = RecordSeriesCap::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(cap.admit_at("mx", "a".into(), start));
+        assert!(cap.admit_at("mx", "b".into(), start));
+        assert!(!cap.admit_at("mx", "c".into(), start));
+        // Admitted records stay admitted, and each type has its own cap
+        assert!(cap.admit_at("mx", "a".into(), start));
+        assert!(cap.admit_at("srv", "c".into(), start));
+
+        // `a` was seen again, so only `b` is idle by now
+        let later = start + Duration::from_secs(90);
+        assert!(cap.admit_at("mx", "a".into(), start + Duration::from_secs(45)));
+        assert!(cap.admit_at("mx", "c".into(), later));
+        assert!(!cap.admit_at("mx", "b".into(), later));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::state;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{LazyLock, Mutex};
+
+const STATE_KEY: &str = "dns_soa";
+
+/// Serials remembered per endpoint, older ones stop being tracked
+const MAX_TRACKED_SERIALS: usize = 16;
+
+/// Locations not seen for this long are forgotten, most nodes only come around now and then
+const LOCATION_RETENTION: chrono::Duration = chrono::Duration::days(1);
+
+/// How often locations are checked against the retention
+const PRUNE_INTERVAL: chrono::Duration = chrono::Duration::minutes(5);
+
+static ZONES: LazyLock<Mutex<Zones>> = LazyLock::new(|| {
+    state::before_save(persist);
+    Mutex::new(Zones {
+        zones: state::get(STATE_KEY).unwrap_or_default(),
+        pruned: Utc::now(),
+    })
+});
+
+struct Zones {
+    zones: HashMap<String, ZoneSerials>,
+    pruned: DateTime<Utc>,
+}
+
+/// A parsed SOA record, e.g. `ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Soa {
+    pub mname: String,
+    pub rname: String,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+impl FromStr for Soa {
+    type Err = ();
+
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = record.split_whitespace().collect();
+
+        // Some resolvers prefix the record with the owner, TTL, class and type
+        let [.., mname, rname, serial, refresh, retry, expire, minimum] = fields[..] else {
+            return Err(());
+        };
+        let number = |field: &str| field.parse::<u32>().map_err(|_| ());
+
+        Ok(Soa {
+            mname: mname.to_string(),
+            rname: rname.to_string(),
+            serial: number(serial)?,
+            refresh: number(refresh)?,
+            retry: number(retry)?,
+            expire: number(expire)?,
+            minimum: number(minimum)?,
+        })
+    }
+}
+
+/// Whether `a` is a newer serial than `b`, using RFC 1982 serial number arithmetic
+pub fn serial_newer(a: u32, b: u32) -> bool {
+    a != b && (a.wrapping_sub(b) as i32) > 0
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct ZoneSerials {
+    highest: Option<u32>,
+    /// When each serial newer than the one seen on boot was first seen anywhere
+    first_seen: HashMap<u32, DateTime<Utc>>,
+    /// Last serial seen from each location
+    locations: HashMap<String, LocationSerial>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct LocationSerial {
+    serial: u32,
+    seen: DateTime<Utc>,
+}
+
+impl ZoneSerials {
+    fn prune(&mut self, now: DateTime<Utc>) {
+        self.locations
+            .retain(|_, location| now - location.seen < LOCATION_RETENTION);
+    }
+}
+
+/// What a single sighting of a serial tells about its propagation
+pub struct Sighting {
+    pub highest: u32,
+    /// Time since the serial was first seen anywhere, when this location just caught up
+    pub propagation_lag: Option<chrono::Duration>,
+}
+
+/// Records a location seeing a serial for a zone.
+///
+/// Propagation lag is only measured for serials first seen after the zone was already
+/// known, so the serial every location returns on boot does not count as a push.
+pub fn observe(endpoint: &str, location: String, serial: u32) -> Sighting {
+    let now = Utc::now();
+    let mut zones = ZONES.lock().unwrap();
+    if now - zones.pruned >= PRUNE_INTERVAL {
+        zones.zones.values_mut().for_each(|zone| zone.prune(now));
+        zones.pruned = now;
+    }
+    let zone = zones.zones.entry(endpoint.to_string()).or_default();
+
+    match zone.highest {
+        Some(highest) if serial_newer(serial, highest) => {
+            zone.highest = Some(serial);
+            zone.first_seen.insert(serial, now);
+
+            if zone.first_seen.len() > MAX_TRACKED_SERIALS {
+                let oldest = zone.first_seen.iter().min_by_key(|(_, seen)| **seen);
+                if let Some((&oldest, _)) = oldest {
+                    zone.first_seen.remove(&oldest);
+                }
+            }
+        }
+        Some(_) => {}
+        None => zone.highest = Some(serial),
+    }
+
+    let previous = zone
+        .locations
+        .insert(location, LocationSerial { serial, seen: now });
+    let caught_up = previous.is_none_or(|previous| serial_newer(serial, previous.serial));
+
+    let propagation_lag = zone
+        .first_seen
+        .get(&serial)
+        .filter(|_| caught_up)
+        .map(|first_seen| now - *first_seen);
+
+    Sighting {
+        highest: zone.highest.unwrap_or(serial),
+        propagation_lag,
+    }
+}
+
+fn persist() {
+    state::put(STATE_KEY, &ZONES.lock().unwrap().zones);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_records() {
+        let soa: Soa = "ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300"
+            .parse()
+            .unwrap();
+
+        assert_eq!(soa.mname, "ns1.example.com.");
+        assert_eq!(soa.rname, "hostmaster.example.com.");
+        assert_eq!(soa.serial, 2024010101);
+        assert_eq!(
+            (soa.refresh, soa.retry, soa.expire, soa.minimum),
+            (7200, 3600, 1209600, 300)
+        );
+
+        let prefixed: Soa =
+            "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300"
+                .parse()
+                .unwrap();
+        assert_eq!(prefixed, soa);
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        assert!("".parse::<Soa>().is_err());
+        assert!(
+            "ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600"
+                .parse::<Soa>()
+                .is_err()
+        );
+        assert!(
+            "ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 -1"
+                .parse::<Soa>()
+                .is_err()
+        );
+        assert!(
+            "ns1.example.com. hostmaster.example.com. 4294967296 7200 3600 1209600 300"
+                .parse::<Soa>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn compares_serials_with_wraparound() {
+        assert!(serial_newer(2, 1));
+        assert!(!serial_newer(1, 2));
+        assert!(!serial_newer(7, 7));
+
+        // Incrementing past u32::MAX wraps around to a newer serial
+        assert!(serial_newer(0, u32::MAX));
+        assert!(serial_newer(5, u32::MAX - 5));
+        assert!(!serial_newer(u32::MAX, 0));
+
+        // Within half the serial space ahead is newer, further ahead is older
+        assert!(serial_newer(1 << 31, 1));
+        assert!(!serial_newer((1 << 31) + 2, 1));
+        // Exactly half apart is undefined, neither is newer
+        assert!(!serial_newer(1 << 31, 0));
+        assert!(!serial_newer(0, 1 << 31));
+    }
+
+    #[test]
+    fn measures_propagation_of_new_serials_only() {
+        let endpoint = "soa-test.example.com";
+
+        let boot = observe(endpoint, "a".to_string(), u32::MAX);
+        assert_eq!(boot.highest, u32::MAX);
+        assert!(boot.propagation_lag.is_none());
+
+        // A serial past the wraparound is a push, and the first location sees it at once
+        let pushed = observe(endpoint, "a".to_string(), 1);
+        assert_eq!(pushed.highest, 1);
+        assert!(pushed.propagation_lag.is_some());
+
+        // A location still on the old serial does not lower the highest
+        let lagging = observe(endpoint, "b".to_string(), u32::MAX);
+        assert_eq!(lagging.highest, 1);
+        assert!(lagging.propagation_lag.is_none());
+
+        let caught_up = observe(endpoint, "b".to_string(), 1);
+        assert!(caught_up.propagation_lag.is_some());
+
+        // Seeing the same serial again is not another catch up
+        let again = observe(endpoint, "b".to_string(), 1);
+        assert!(again.propagation_lag.is_none());
+    }
+
+    #[test]
+    fn forgets_idle_locations() {
+        let now = Utc::now();
+        let mut zone = ZoneSerials::default();
+        zone.locations.insert(
+            "idle".to_string(),
+            LocationSerial {
+                serial: 1,
+                seen: now - LOCATION_RETENTION,
+            },
+        );
+        zone.locations.insert(
+            "recent".to_string(),
+            LocationSerial {
+                serial: 1,
+                seen: now - chrono::Duration::hours(1),
+            },
+        );
+
+        zone.prune(now);
+        assert_eq!(zone.locations.keys().collect::<Vec<_>>(), ["recent"]);
+    }
+}
@@ -1,5 +1,6 @@
-use super::{Collector, CollectorErrors};
-use crate::config::HlsConfig;
+use super::{Collector, CollectorErrors, ProbeOutcome};
+use crate::assertions::{probe_facts, Fact, Facts};
+use crate::config::{HlsConfig, MetricConfig};
 use crate::types::*;
 use crate::API_CLIENT;
 use color_eyre::eyre::Result;
@@ -28,10 +29,16 @@ impl Collector for HlsCollector {
     type Config = HlsConfig;
     type Response = PerformHlsResponse;
 
+    const PROTOCOL: &'static str = "hls";
+
     fn new(config: &'static HlsConfig) -> Self {
         Self { config }
     }
 
+    fn common_config(&self) -> &'static MetricConfig {
+        &self.config.common_config
+    }
+
     fn register_metrics(&self) {
         let prefix = &self.config.common_config.prefix;
 
@@ -183,7 +190,62 @@ impl Collector for HlsCollector {
         Ok(response.into_inner())
     }
 
-fn handle_response(&self, response: PerformHlsResponse) -> Result<(), CollectorErrors> {
+    fn facts(&self, response: &PerformHlsResponse) -> Facts {
+        let Some(result) = response.results.first() else {
+            return Facts::new();
+        };
+        let mut facts = probe_facts(result.duration, result.error.as_ref());
+
+        let Some(hls_result) = &result.result else {
+            return facts;
+        };
+
+        let mut ratios = Vec::new();
+
+        if let Some(master) = &hls_result.master {
+            facts.insert(
+                "renditions_count",
+                Fact::Number(master.renditions.len() as f64),
+            );
+
+            if let Some(download_metrics) = &master.download_metrics {
+                facts.insert("master_download_ms", Fact::Number(download_metrics.time_ms));
+            }
+            if let Some(metrics) = &master.metrics {
+                facts.insert(
+                    "master_ttfb_ms",
+                    Fact::Number(metrics.http_ttfb_duration_ms),
+                );
+            }
+
+            for rendition in &master.renditions {
+                ratios.extend(
+                    rendition
+                        .content_fragment_metrics
+                        .iter()
+                        .map(|f| f.download_ratio),
+                );
+            }
+        }
+
+        if let Some(rendition) = &hls_result.rendition {
+            ratios.extend(
+                rendition
+                    .content_fragment_metrics
+                    .iter()
+                    .map(|f| f.download_ratio),
+            );
+        }
+
+        facts.insert("fragments_count", Fact::Number(ratios.len() as f64));
+        if let Some(min_ratio) = ratios.into_iter().reduce(f64::min) {
+            facts.insert("min_download_ratio", Fact::Number(min_ratio));
+        }
+
+        facts
+    }
+
+fn handle_response(&self, response: PerformHlsResponse) -> Result<ProbeOutcome, CollectorErrors> {
         let prefix = &self.config.common_config.prefix;
         let endpoint = self
             .config
@@ -221,7 +283,7 @@ fn handle_response(&self, response: PerformHlsResponse) -> Result<(), CollectorE
                     // Handle error case
                     error!("HLS error occurred: {}", error);
                     self.record_failure_with_labels(error, &labels);
-                    return Ok(());
+                    return Ok(ProbeOutcome::Failure);
                 }
 
                 if let Some(hls_result) = &result.result {
@@ -245,7 +307,7 @@ fn handle_response(&self, response: PerformHlsResponse) -> Result<(), CollectorE
                         self.record_rendition_metrics(&labels, None, rendition)?;
                     }
 
-                    Ok(())
+                    Ok(ProbeOutcome::Success)
                 } else {
                     Err(CollectorErrors::MissingData(endpoint.clone(), "hls_result"))
                 }
@@ -1,5 +1,6 @@
-use super::{Collector, CollectorErrors};
-use crate::config::{HttpConfig, LookupTypes};
+use super::{Collector, CollectorErrors, ProbeOutcome};
+use crate::assertions::{probe_facts, Fact, Facts};
+use crate::config::{HttpConfig, LookupTypes, MetricConfig};
 use crate::types::{
     PerformHttpBodyConfiguration, PerformHttpBodyContinentCode, PerformHttpBodyCountryCode,
     PerformHttpBodyMobile, PerformHttpBodyProxy, PerformHttpBodyResidential, PerformHttpResponse,
@@ -24,10 +25,16 @@ impl Collector for HttpCollector {
     type Config = HttpConfig;
     type Response = PerformHttpResponse;
 
+    const PROTOCOL: &'static str = "http";
+
     fn new(config: &'static HttpConfig) -> Self {
         Self { config }
     }
 
+    fn common_config(&self) -> &'static MetricConfig {
+        &self.config.common_config
+    }
+
     fn register_metrics(&self) {
         let prefix = &self.config.common_config.prefix;
 
@@ -155,7 +162,29 @@ impl Collector for HttpCollector {
         self.config.common_config.frequency
     }
 
-    fn handle_response(&self, response: PerformHttpResponse) -> Result<(), CollectorErrors> {
+    fn facts(&self, response: &PerformHttpResponse) -> Facts {
+        let Some(result) = response.results.first() else {
+            return Facts::new();
+        };
+        let mut facts = probe_facts(result.duration, result.error.as_ref());
+
+        if let Some(http_result) = &result.result {
+            facts.insert("status_code", Fact::Number(http_result.status_code));
+            facts.insert("body_hash", Fact::Text(http_result.body_hash.clone()));
+            facts.insert("matches", Fact::List(http_result.matches.clone()));
+            facts.insert("match_count", Fact::Number(http_result.matches.len() as f64));
+            if let Some(body) = &http_result.body {
+                facts.insert("body", Fact::Text(body.clone()));
+            }
+        }
+
+        facts
+    }
+
+    fn handle_response(
+        &self,
+        response: PerformHttpResponse,
+    ) -> Result<ProbeOutcome, CollectorErrors> {
         let endpoint = self
             .config
             .common_config
@@ -186,13 +215,15 @@ impl Collector for HttpCollector {
             labels.insert("geohash", v);
         }
 
-        if let Some(result) = response.results.first() {
+        let outcome = if let Some(result) = response.results.first() {
             if let Some(error) = &result.error {
                 // Handle error case
                 self.record_failure_with_labels(error, &labels);
+                ProbeOutcome::Failure
             } else if let Some(http_result) = &result.result {
                 // Extract status code and other metrics from the HTTP result
                 self.record_success_metrics(http_result, result.duration.unwrap_or(0.0), &labels);
+                ProbeOutcome::Success
             } else {
                 error!("Missing http result data");
                 return Err(CollectorErrors::MissingData(
@@ -203,9 +234,9 @@ impl Collector for HttpCollector {
         } else {
             error!("No results returned from API");
             return Err(CollectorErrors::MissingData(endpoint.clone(), "no_results"));
-        }
+        };
 
-        Ok(())
+        Ok(outcome)
     }
 }
 
@@ -1,5 +1,7 @@
-use super::{Collector, CollectorErrors};
-use crate::config::IcmpConfig;
+use super::{Collector, CollectorErrors, ProbeOutcome};
+use crate::asn;
+use crate::assertions::{probe_facts, Fact, Facts};
+use crate::config::{IcmpConfig, MetricConfig};
 use crate::types::{
     PerformIcmpBodyContinentCode, PerformIcmpBodyCountryCode, PerformIcmpBodyMobile,
     PerformIcmpBodyProxy, PerformIcmpBodyResidential, PerformIcmpResponse,
@@ -21,10 +23,16 @@ impl Collector for IcmpCollector {
     type Config = IcmpConfig;
     type Response = PerformIcmpResponse;
 
+    const PROTOCOL: &'static str = "icmp";
+
     fn new(config: &'static IcmpConfig) -> Self {
         Self { config }
     }
 
+    fn common_config(&self) -> &'static MetricConfig {
+        &self.config.common_config
+    }
+
     fn register_metrics(&self) {
         let prefix = &self.config.common_config.prefix;
 
@@ -154,7 +162,30 @@ impl Collector for IcmpCollector {
         self.config.common_config.frequency
     }
 
-    fn handle_response(&self, response: PerformIcmpResponse) -> Result<(), CollectorErrors> {
+    fn facts(&self, response: &PerformIcmpResponse) -> Facts {
+        let Some(result) = response.results.first() else {
+            return Facts::new();
+        };
+        let mut facts = probe_facts(result.duration, result.error.as_ref());
+
+        if let Some(icmp_result) = &result.result {
+            facts.insert("packet_loss", Fact::Number(icmp_result.packet_loss));
+            facts.insert("avg_ms", Fact::Number(icmp_result.avg));
+            facts.insert("min_ms", Fact::Number(icmp_result.min));
+            facts.insert("max_ms", Fact::Number(icmp_result.max));
+            facts.insert("stddev_ms", Fact::Number(icmp_result.std_dev));
+            facts.insert("packets_sent", Fact::Number(icmp_result.packets_sent));
+            facts.insert("packets_received", Fact::Number(icmp_result.packets_recv));
+            facts.insert("ip_address", Fact::Text(icmp_result.ip_address.clone()));
+        }
+
+        facts
+    }
+
+    fn handle_response(
+        &self,
+        response: PerformIcmpResponse,
+    ) -> Result<ProbeOutcome, CollectorErrors> {
         let endpoint = self
             .config
             .common_config
@@ -185,7 +216,7 @@ impl Collector for IcmpCollector {
             labels.insert("geohash", v);
         }
 
-        if let Some(result) = response.results.first() {
+        let outcome = if let Some(result) = response.results.first() {
             if let Some(error) = &result.error {
                 // Record the specific error from the ICMP response
                 self.record_failure_with_labels(error, &labels);
@@ -194,22 +225,37 @@ impl Collector for IcmpCollector {
             if let Some(icmp_result) = &result.result {
                 // Add IP address to labels
                 labels.insert("ip_address", icmp_result.ip_address.clone());
+                if let Some(network) = asn::lookup_str(&icmp_result.ip_address) {
+                    network.label(&mut labels);
+                }
 
                 // Record metrics only if we have valid results
                 self.record_success_metrics(result, icmp_result, &labels);
-            } else {
+            } else if result.error.is_none() {
                 error!("Missing ICMP result data");
                 return Err(CollectorErrors::MissingData(
                     endpoint.clone(),
                     "icmp_result",
                 ));
             }
+
+            // No replies at all is a failed probe even without an error
+            let unreachable = result
+                .result
+                .as_ref()
+                .is_some_and(|r| r.packets_recv == 0.0);
+
+            if result.error.is_some() || unreachable {
+                ProbeOutcome::Failure
+            } else {
+                ProbeOutcome::Success
+            }
         } else {
             error!("No results returned from API");
             return Err(CollectorErrors::MissingData(endpoint.clone(), "no_results"));
-        }
+        };
 
-        Ok(())
+        Ok(outcome)
     }
 }
 
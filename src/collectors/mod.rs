@@ -1,4 +1,8 @@
+use crate::assertions::{self, Facts};
+use crate::config::MetricConfig;
+use crate::events;
 use color_eyre::eyre::Result;
+use serde::Serialize;
 use std::time::Duration;
 use thiserror::Error;
 
@@ -21,19 +25,33 @@ pub enum CollectorErrors {
     MissingData(String, &'static str),
 }
 
+/// Whether a probe succeeded, as judged by the collector that processed it
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeOutcome {
+    Success,
+    Failure,
+}
+
 /// A trait for implementing metric collectors
 ///
 /// Collectors are responsible for gathering metrics at regular intervals
 /// and processing the results.
 pub trait Collector {
     type Config;
-    type Response;
+    type Response: Serialize;
+
+    /// Metric family name this collector emits under, e.g. `dns`
+    const PROTOCOL: &'static str;
 
     /// Creates a new instance of the collector
     fn new(config: &'static Self::Config) -> Self
     where
         Self: Sized;
 
+    /// Returns the configuration shared by every check type
+    fn common_config(&self) -> &'static MetricConfig;
+
     /// Registers metrics with the metrics system
     fn register_metrics(&self);
     /// Performs the actual metric collection request
@@ -42,8 +60,11 @@ pub trait Collector {
     /// Returns the frequency at which this collector should run
     fn get_frequency(&self) -> Duration;
 
-    /// Handles the response from a successful request
-    fn handle_response(&self, response: Self::Response) -> Result<(), CollectorErrors>;
+    /// Extracts the values a check's assertions are evaluated against
+    fn facts(&self, response: &Self::Response) -> Facts;
+
+    /// Handles the response from a successful request, returning the outcome of the probe
+    fn handle_response(&self, response: Self::Response) -> Result<ProbeOutcome, CollectorErrors>;
 
     /// Handles any errors that occur during collection
     fn handle_errors(&self, error: CollectorErrors) -> Result<()> {
@@ -54,6 +75,7 @@ pub trait Collector {
     /// Runs the collector in a loop with proper error handling and shutdown capability
     async fn run(&self) -> Result<()> {
         self.register_metrics();
+        assertions::describe(self.common_config());
 
         loop {
             let request_future = self.perform_request();
@@ -62,11 +84,25 @@ pub trait Collector {
             match tokio::time::timeout(timeout_duration, request_future).await {
                 Ok(result) => match result {
                     Ok(response) => {
-                        if let Err(e) = self.handle_response(response) {
-                            self.handle_errors(e)?;
-                        }
+                        let raw = events::capture(&response);
+                        let facts = self.facts(&response);
+                        let outcome = match self.handle_response(response) {
+                            Ok(outcome) => Some(outcome),
+                            Err(e) => {
+                                self.handle_errors(e)?;
+                                None
+                            }
+                        };
+                        let outcome = assertions::evaluate(self.common_config(), &facts, outcome);
+                        events::publish_response(
+                            Self::PROTOCOL,
+                            self.common_config(),
+                            raw,
+                            outcome,
+                        );
                     }
                     Err(e) => {
+                        events::publish_error(Self::PROTOCOL, self.common_config(), e.to_string());
                         self.handle_errors(CollectorErrors::Measurement {
                             metric: "unknown".to_string(),
                             reason: e.to_string(),
@@ -74,10 +110,17 @@ pub trait Collector {
                     }
                 },
                 Err(_) => {
+                    events::publish_error(
+                        Self::PROTOCOL,
+                        self.common_config(),
+                        format!("Request timeout after {timeout_duration:?}"),
+                    );
                     self.handle_errors(CollectorErrors::Timeout(timeout_duration))?;
                 }
             }
 
+            crate::pushgateway::after_run(self.common_config()).await;
+
             tokio::time::sleep(self.get_frequency()).await;
         }
     }
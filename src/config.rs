@@ -1,13 +1,21 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU32,
+    path::PathBuf,
+    time::Duration,
+};
 
 use regress::Regex;
 use serde::Deserialize;
 
-use eyre::{Context, Result};
+use crate::assertions::Assertion;
+use crate::relabel::RelabelRegex;
+use eyre::{bail, Context, Result};
 use figment::{
     providers::{Env, Format, Yaml},
     Figment,
 };
+use ipnet::IpNet;
 use serde_json::Value;
 use strum::{AsRefStr, EnumString};
 
@@ -25,12 +33,364 @@ pub struct GlobalConfig {
     #[serde(with = "humantime_serde")]
     #[serde(default = "default_metric_clear_timeout")]
     pub metric_clear_timeout: Duration,
+
+    #[serde(default)]
+    pub pushgateway: Option<PushgatewayConfig>,
+
+    #[serde(default)]
+    pub archive: Option<ArchiveConfig>,
+
+    #[serde(default)]
+    pub kafka: Option<KafkaConfig>,
+
+    #[serde(default)]
+    pub loki: Option<LokiConfig>,
+
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+
+    #[serde(default)]
+    pub state: Option<StateConfig>,
+
+    /// Rules applied to every series, after the check's own rules
+    #[serde(default)]
+    pub relabel_configs: Vec<RelabelConfig>,
+
+    /// Default labels attached to every check's series, overridden by the check's own
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+
+    #[serde(default)]
+    pub histograms: HistogramConfig,
+
+    #[serde(default)]
+    pub dns_providers: Option<ProviderCatalogConfig>,
+
+    #[serde(default)]
+    pub asn_database: Option<AsnDatabaseConfig>,
+}
+
+/// Local MaxMind or IPinfo format database used to label addresses with their AS
+#[derive(Deserialize, Clone, Debug)]
+pub struct AsnDatabaseConfig {
+    /// `.mmdb` file with ASN records, such as GeoLite2-ASN or IPinfo's ASN database
+    pub path: PathBuf,
+}
+
+/// Catalog of resolver ranges used to name the provider behind a DNS server
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProviderCatalogConfig {
+    /// YAML or JSON file of providers and their CIDR ranges, reloaded when it changes
+    pub path: PathBuf,
+    /// How often the file is checked for changes
+    #[serde(default = "default_provider_reload_interval", with = "humantime_serde")]
+    pub reload_interval: Duration,
+    /// Whether the bundled catalog is kept underneath the file's entries
+    #[serde(default = "default_true")]
+    pub include_bundled: bool,
+}
+
+fn default_provider_reload_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// How histograms are exposed. Families without buckets are rendered as summaries.
+#[derive(Deserialize, Clone, Default, Debug)]
+pub struct HistogramConfig {
+    /// Buckets for every histogram family without its own
+    #[serde(default)]
+    pub buckets: Option<Buckets>,
+    /// Buckets per metric family, matched against the end of the metric name so the
+    /// check's prefix can be left out
+    #[serde(default)]
+    pub families: HashMap<String, Buckets>,
+    /// Quantiles of the families rendered as summaries
+    #[serde(default)]
+    pub quantiles: Option<Vec<f64>>,
+    /// Period summaries are computed over
+    #[serde(default, with = "humantime_serde")]
+    pub summary_window: Option<Duration>,
+    /// Number of buckets the summary window rolls over in
+    #[serde(default)]
+    pub summary_age_buckets: Option<NonZeroU32>,
+}
+
+/// Upper bounds of histogram buckets, listed explicitly or generated
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Buckets {
+    Explicit(Vec<f64>),
+    Exponential { exponential: ExponentialBuckets },
+    Linear { linear: LinearBuckets },
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ExponentialBuckets {
+    pub start: f64,
+    pub factor: f64,
+    pub count: usize,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LinearBuckets {
+    pub start: f64,
+    pub width: f64,
+    pub count: usize,
+}
+
+impl Buckets {
+    pub fn bounds(&self) -> Vec<f64> {
+        match self {
+            Buckets::Explicit(bounds) => bounds.clone(),
+            Buckets::Exponential { exponential: e } => (0..e.count)
+                .map(|i| e.start * e.factor.powi(i as i32))
+                .collect(),
+            Buckets::Linear { linear: l } => {
+                (0..l.count).map(|i| l.start + l.width * i as f64).collect()
+            }
+        }
+    }
+}
+
+/// A Prometheus-style relabel rule applied to series before they are recorded
+#[derive(Deserialize, Clone, Debug)]
+pub struct RelabelConfig {
+    #[serde(default)]
+    pub source_labels: Vec<String>,
+    #[serde(default = "default_relabel_separator")]
+    pub separator: String,
+    #[serde(default)]
+    pub regex: RelabelRegex,
+    /// Required by the `replace` and `hashmod` actions
+    #[serde(default)]
+    pub target_label: Option<String>,
+    #[serde(default = "default_relabel_replacement")]
+    pub replacement: String,
+    /// Required by the `hashmod` action
+    #[serde(default)]
+    pub modulus: Option<u64>,
+    #[serde(default)]
+    pub action: RelabelAction,
+}
+
+fn default_relabel_separator() -> String {
+    ";".to_string()
+}
+
+fn default_relabel_replacement() -> String {
+    "$1".to_string()
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RelabelAction {
+    #[default]
+    Replace,
+    Keep,
+    Drop,
+    Labelmap,
+    Labeldrop,
+    Labelkeep,
+    Hashmod,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct StateConfig {
+    /// File the state snapshot is written to and restored from on boot
+    pub path: PathBuf,
+    /// How often the snapshot is written, it is always written on shutdown
+    #[serde(default = "default_state_interval", with = "humantime_serde")]
+    pub interval: Duration,
+}
+
+fn default_state_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PushgatewayConfig {
+    pub url: String,
+    #[serde(default = "default_pushgateway_job")]
+    pub job: String,
+    #[serde(default)]
+    pub push_on: PushTrigger,
+    #[serde(default)]
+    pub delete_on_shutdown: bool,
+}
+
+fn default_pushgateway_job() -> String {
+    "distributed_metrics".to_string()
+}
+
+/// When the contents of the registry are pushed to the Pushgateway
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PushTrigger {
+    /// Push a check's series every time its collector finishes a run
+    #[default]
+    EachRun,
+    /// Push every check's series once, when the process is shutting down
+    Exit,
 }
 
 fn default_metric_clear_timeout() -> Duration {
     Duration::from_secs(10)
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct ArchiveConfig {
+    /// Directory the archive files are written to
+    pub path: PathBuf,
+    #[serde(default)]
+    pub format: ArchiveFormat,
+    /// Start a new file once the current one reaches this many bytes
+    #[serde(default = "default_archive_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// Start a new file once the current one is this old
+    #[serde(default, with = "humantime_serde")]
+    pub rotate_interval: Option<Duration>,
+    /// Delete archive files older than this
+    #[serde(default, with = "humantime_serde")]
+    pub retention: Option<Duration>,
+    /// Keep at most this many archive files
+    #[serde(default)]
+    pub max_files: Option<usize>,
+    /// Flush buffered JSONL lines to disk at least this often
+    #[serde(default = "default_archive_flush_interval", with = "humantime_serde")]
+    pub flush_interval: Duration,
+}
+
+fn default_archive_max_file_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_archive_flush_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    #[default]
+    Jsonl,
+    Parquet,
+}
+
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+#[derive(Deserialize, Clone, Debug)]
+pub struct KafkaConfig {
+    /// Comma separated list of `host:port` bootstrap servers
+    pub brokers: String,
+    pub topic: String,
+    #[serde(default)]
+    pub key: KafkaKey,
+    #[serde(default)]
+    pub format: RecordFormat,
+    #[serde(default = "default_true")]
+    pub idempotent: bool,
+    /// Records buffered locally while the broker is unavailable
+    #[serde(default = "default_kafka_buffer_messages")]
+    pub buffer_messages: u32,
+    /// Give up on buffered records after this long, retries forever if unset
+    #[serde(default, with = "humantime_serde")]
+    pub delivery_timeout: Option<Duration>,
+    /// Extra librdkafka producer properties, e.g. `security.protocol`
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_kafka_buffer_messages() -> u32 {
+    100_000
+}
+
+/// Which field a Kafka record is keyed, and therefore partitioned, by
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KafkaKey {
+    #[default]
+    Endpoint,
+    Check,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordFormat {
+    #[default]
+    Json,
+    Avro,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LokiConfig {
+    /// Base URL of Loki, e.g. `http://loki:3100`
+    pub url: String,
+    /// Sent as `X-Scope-OrgID` for multi-tenant Loki
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    /// Static labels added to every stream
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default = "default_loki_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_loki_batch_interval", with = "humantime_serde")]
+    pub batch_interval: Duration,
+}
+
+fn default_loki_batch_size() -> usize {
+    500
+}
+
+fn default_loki_batch_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub template: WebhookTemplate,
+    /// Custom JSON payload with `{{placeholder}}` values, overrides `template`
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Only notify for these check ids, all checks if empty
+    #[serde(default)]
+    pub checks: Vec<String>,
+    /// Consecutive failed probes before a check is considered down
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Consecutive successful probes before a down check is considered recovered
+    #[serde(default = "default_recovery_threshold")]
+    pub recovery_threshold: u32,
+    /// Notify again at this interval while a check stays down
+    #[serde(default, with = "humantime_serde")]
+    pub repeat_interval: Option<Duration>,
+    #[serde(default = "default_true")]
+    pub send_resolved: bool,
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+fn default_recovery_threshold() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookTemplate {
+    #[default]
+    Generic,
+    Slack,
+    Discord,
+}
+
 #[derive(Deserialize, AsRefStr, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
@@ -41,6 +401,36 @@ pub enum MetricType {
     Http(HttpConfig),
 }
 
+impl MetricType {
+    /// Name of the metric family this check emits under, e.g. `dns`
+    pub fn protocol(&self) -> &'static str {
+        match self {
+            MetricType::Dns(_) => "dns",
+            MetricType::Icmp(_) => "icmp",
+            MetricType::Hls(_) => "hls",
+            MetricType::Http(_) => "http",
+        }
+    }
+
+    pub fn common_config(&self) -> &MetricConfig {
+        match self {
+            MetricType::Dns(c) => &c.common_config,
+            MetricType::Icmp(c) => &c.common_config,
+            MetricType::Hls(c) => &c.common_config,
+            MetricType::Http(c) => &c.common_config,
+        }
+    }
+
+    fn common_config_mut(&mut self) -> &mut MetricConfig {
+        match self {
+            MetricType::Dns(c) => &mut c.common_config,
+            MetricType::Icmp(c) => &mut c.common_config,
+            MetricType::Hls(c) => &mut c.common_config,
+            MetricType::Http(c) => &mut c.common_config,
+        }
+    }
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Deserialize, AsRefStr, Clone, Debug)]
 pub enum HttpMethod {
@@ -78,12 +468,93 @@ pub struct HlsConfig {
 pub struct DnsConfig {
     #[serde(flatten)]
     pub common_config: MetricConfig,
+    /// Single lookup type, kept for existing configs. Combined with `lookup_types`.
+    #[serde(default)]
+    pub lookup_type: Option<LookupTypes>,
+    /// Record types looked up together in every probe (default: IP)
+    #[serde(default)]
+    pub lookup_types: Vec<LookupTypes>,
+    /// Resolvers to query instead of the node's own: IPs, optionally as `udp:`/`tcp:`
+    /// with a port, or provider presets such as `google`, `cloudflare` or `quad9`
+    #[serde(default)]
+    pub dns_servers: Vec<String>,
+    /// Answers each lookup type should return, anything else counts as a mismatch
     #[serde(default)]
-    pub lookup_type: LookupTypes,
+    pub expect: HashMap<LookupTypes, ExpectedAnswer>,
+    /// Flag locations whose answers deviate from what the other locations see
+    #[serde(default)]
+    pub consensus: Option<ConsensusConfig>,
+    /// Query a random label under the endpoint, which must not have a wildcard record,
+    /// and report resolvers that answer it instead of returning NXDOMAIN
+    #[serde(default)]
+    pub nxdomain_canary: bool,
+    /// Domain with a wildcard record, e.g. `cb.example.com` for `*.cb.example.com`. Every
+    /// probe also looks up a random label under it, which no resolver has cached.
+    #[serde(default)]
+    pub cache_busting_domain: Option<String>,
+    /// Check the endpoint's MX, SPF, DMARC and DKIM records
+    #[serde(default)]
+    pub email_auth: Option<EmailAuthConfig>,
+    /// MX, SRV and TLSA info series exported per record type, one for each record and
+    /// country it was seen from
+    #[serde(default = "default_max_record_series")]
+    pub max_record_series: usize,
+}
+
+fn default_max_record_series() -> usize {
+    500
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct EmailAuthConfig {
+    /// DKIM selectors whose keys must be published, e.g. `google` or `selector1`
+    #[serde(default)]
+    pub dkim_selectors: Vec<String>,
+}
+
+/// Consensus of answers built across vantage points, instead of hand-maintained expectations
+#[derive(Deserialize, Clone, Debug)]
+pub struct ConsensusConfig {
+    /// Answers older than this no longer count towards the consensus
+    #[serde(default = "default_consensus_window", with = "humantime_serde")]
+    pub window: Duration,
+    /// Locations needed in the window before answers are compared with the consensus.
+    /// Private and bogon answers are always flagged.
+    #[serde(default = "default_consensus_min_locations")]
+    pub min_locations: usize,
+    /// Share of locations an answer, or its ASN, must be seen from to be part of the consensus
+    #[serde(default = "default_consensus_share")]
+    pub share: f64,
+}
+
+fn default_consensus_window() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
+fn default_consensus_min_locations() -> usize {
+    3
+}
+
+fn default_consensus_share() -> f64 {
+    0.5
+}
+
+/// An expected answer, either the exact record set or the records allowed in it
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct ExpectedAnswer {
+    /// The record set must be exactly this, ignoring order and case
+    #[serde(default)]
+    pub exact: Option<Vec<String>>,
+    /// Every IP returned must be within one of these networks
+    #[serde(default)]
+    pub cidrs: Vec<IpNet>,
+    /// Every record returned must match one of these patterns
+    #[serde(default, with = "serde_regex")]
+    pub patterns: Vec<regex::Regex>,
 }
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Deserialize, AsRefStr, Clone, Debug, Default)]
+#[derive(Deserialize, AsRefStr, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub enum LookupTypes {
     #[default]
     IP,
@@ -103,6 +574,8 @@ pub struct IcmpConfig {
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct MetricConfig {
+    #[serde(default)]
+    pub id: Option<String>,
     #[serde(default)]
     pub prefix: String,
     #[serde(default)]
@@ -112,6 +585,99 @@ pub struct MetricConfig {
     pub frequency: Duration,
 
     pub network: Option<NetworkCriteria>,
+
+    #[serde(default)]
+    pub slo: Option<SloConfig>,
+
+    #[serde(default)]
+    pub quorum: Option<QuorumConfig>,
+
+    /// Conditions every probe must meet to count as successful, e.g. `latency_ms < 300`
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+
+    /// Rules applied to this check's series, before the global rules
+    #[serde(default)]
+    pub relabel_configs: Vec<RelabelConfig>,
+
+    /// Labels attached to every series of this check, values may use `{{endpoint_host}}`
+    /// and the other check fields as placeholders
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SloConfig {
+    /// Percentage of probes that must succeed, e.g. `99.9`
+    pub target: f64,
+    #[serde(default = "default_slo_window", with = "humantime_serde")]
+    pub window: Duration,
+    #[serde(default)]
+    pub success: SloSuccess,
+    /// Also evaluate the SLO separately for each continent probes ran from
+    #[serde(default)]
+    pub per_continent: bool,
+}
+
+fn default_slo_window() -> Duration {
+    Duration::from_secs(30 * 24 * 60 * 60)
+}
+
+/// What counts as a good probe, on top of the collector reporting success
+#[derive(Deserialize, Clone, Default, Debug)]
+pub struct SloSuccess {
+    /// Probes slower than this count against the error budget
+    #[serde(default)]
+    pub max_duration_ms: Option<f64>,
+}
+
+/// Marks an endpoint down only when enough distinct locations fail
+#[derive(Deserialize, Clone, Debug)]
+pub struct QuorumConfig {
+    /// Number of failing locations that marks the endpoint down
+    pub failures: usize,
+    /// Number of most recently probed locations the decision is based on
+    pub locations: usize,
+    #[serde(default)]
+    pub by: QuorumLocation,
+    /// Results older than this are no longer considered
+    #[serde(default = "default_quorum_window", with = "humantime_serde")]
+    pub window: Duration,
+}
+
+fn default_quorum_window() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+/// What makes two vantage points distinct locations
+#[derive(Deserialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum QuorumLocation {
+    #[default]
+    Country,
+    Isp,
+    Geohash,
+}
+
+impl MetricConfig {
+    /// Identifier used to group this check's series outside of the scrape endpoint.
+    /// Defaults to `<protocol>:<endpoint label>` when the config is loaded.
+    pub fn check_id(&self) -> &str {
+        self.id.as_deref().unwrap_or_else(|| self.endpoint_label())
+    }
+
+    /// Value of the `endpoint` label attached to every series of this check
+    pub fn endpoint_label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.endpoint)
+    }
+
+    /// Host of the endpoint, which is the endpoint itself for DNS and ICMP checks
+    pub fn endpoint_host(&self) -> String {
+        reqwest::Url::parse(&self.endpoint)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| self.endpoint.clone())
+    }
 }
 
 #[derive(Deserialize, EnumString, AsRefStr, Clone, Default, Debug)]
@@ -150,10 +716,173 @@ pub struct NetworkCriteria {
 
 impl Conf {
     pub fn new() -> Result<Self> {
-        Figment::new()
-            .join(Env::prefixed("BITPING_"))
-            .merge(Yaml::file("Metrics.yaml"))
-            .extract()
-            .context("Unable to read config file")
+        Self::load(
+            Figment::new()
+                .join(Env::prefixed("BITPING_"))
+                .merge(Yaml::file("Metrics.yaml")),
+        )
+    }
+
+    fn load(figment: Figment) -> Result<Self> {
+        let mut conf: Conf = figment.extract().context("Unable to read config file")?;
+
+        // Checks of different protocols often probe the same endpoint, so the protocol is
+        // part of the default id
+        for metric in &mut conf.metrics {
+            let protocol = metric.protocol();
+            let check = metric.common_config_mut();
+            if check.id.is_none() {
+                check.id = Some(format!("{}:{}", protocol, check.endpoint_label()));
+            }
+        }
+
+        conf.validate().context("Invalid config file")?;
+        Ok(conf)
+    }
+
+    /// Checks that cannot be expressed in the config types themselves
+    fn validate(&self) -> Result<()> {
+        // The exporter tries suffix matchers in lexical order rather than longest first, so
+        // a histogram matching two families would not get the more specific buckets
+        let families = &self.global_config.histograms.families;
+        for family in families.keys() {
+            if let Some(overlapping) = families
+                .keys()
+                .find(|other| *other != family && family.ends_with(other.as_str()))
+            {
+                bail!(
+                    "Histogram family {:?} also matches {:?}, families must not end with one another",
+                    family,
+                    overlapping
+                );
+            }
+        }
+
+        let mut check_ids = HashSet::new();
+
+        for metric in &self.metrics {
+            let check = metric.common_config();
+
+            // Series, state and pushes are all keyed by the check id
+            if !check_ids.insert(check.check_id()) {
+                bail!(
+                    "Duplicate check id {:?}, set a unique `id` or `name` on each check",
+                    check.check_id()
+                );
+            }
+
+            // A ratio such as 0.999 would silently mean a tiny percentage
+            if let Some(slo) = &check.slo {
+                if !(slo.target > 0.0 && slo.target < 100.0) {
+                    bail!(
+                        "Check {:?} has an SLO target of {}, it must be a percentage above 0 and below 100",
+                        check.check_id(),
+                        slo.target
+                    );
+                }
+            }
+
+            if let Some(quorum) = &check.quorum {
+                if quorum.failures == 0 || quorum.failures > quorum.locations {
+                    bail!(
+                        "Check {:?} has a quorum of {} failures out of {} locations, it must be between 1 and the number of locations",
+                        check.check_id(),
+                        quorum.failures,
+                        quorum.locations
+                    );
+                }
+            }
+
+            if let MetricType::Dns(dns) = metric {
+                dns.validate()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl DnsConfig {
+    fn validate(&self) -> Result<()> {
+        let check_id = self.common_config.check_id();
+
+        if let Some(consensus) = &self.consensus {
+            if !(consensus.share > 0.0 && consensus.share <= 1.0) {
+                bail!(
+                    "Check {:?} has a consensus share of {}, it must be above 0 and at most 1",
+                    check_id,
+                    consensus.share
+                );
+            }
+        }
+
+        // The canary lookup forces the lookup types and replaces the endpoint's results
+        if self.nxdomain_canary && self.cache_busting_domain.is_some() {
+            bail!(
+                "Check {:?} sets both `nxdomain_canary` and `cache_busting_domain`, use separate checks",
+                check_id
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_the_example_config() {
+        let conf = Conf::load(Figment::new().merge(Yaml::file("Metrics.example.yaml"))).unwrap();
+
+        let ids: Vec<&str> = conf
+            .metrics
+            .iter()
+            .map(|metric| metric.common_config().check_id())
+            .collect();
+        assert!(ids.contains(&"dns:bitping.com"));
+        assert!(ids.contains(&"icmp:bitping.com"));
+    }
+
+    #[test]
+    fn rejects_duplicate_check_ids() {
+        let yaml = "
+metrics:
+  - type: icmp
+    id: edge
+    endpoint: a.example.com
+    frequency: 1s
+  - type: icmp
+    id: edge
+    endpoint: b.example.com
+    frequency: 1s
+";
+        let Err(error) = Conf::load(Figment::new().merge(Yaml::string(yaml))) else {
+            panic!("duplicate check ids were accepted");
+        };
+        assert!(format!("{:?}", error).contains("Duplicate check id \"edge\""));
+    }
+
+    #[test]
+    fn rejects_slo_targets_outside_percentages() {
+        for target in ["0", "100", "-1", ".nan"] {
+            let yaml = format!(
+                "
+metrics:
+  - type: icmp
+    endpoint: example.com
+    frequency: 1s
+    slo:
+      target: {}
+",
+                target
+            );
+            assert!(
+                Conf::load(Figment::new().merge(Yaml::string(&yaml))).is_err(),
+                "target {} was accepted",
+                target
+            );
+        }
     }
 }
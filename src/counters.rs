@@ -0,0 +1,194 @@
+use crate::state;
+use metrics::{
+    Counter, CounterFn, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+};
+use metrics_util::layers::Layer;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+const STATE_KEY: &str = "counters";
+
+/// Totals kept at most, the ones updated least recently are forgotten first. Series come
+/// and go with resolver addresses, locations and relabel rules, so the map would otherwise
+/// keep every series ever seen.
+const MAX_SERIES: usize = 50_000;
+
+/// Running total of every counter series, keyed by [`series_id`]
+static TOTALS: LazyLock<Mutex<HashMap<String, Arc<Total>>>> = LazyLock::new(Default::default);
+
+#[derive(Default)]
+struct Total {
+    value: AtomicU64,
+    /// Unix time of the last update, or of the restore
+    updated: AtomicI64,
+}
+
+impl Total {
+    fn new(value: u64) -> Self {
+        Self {
+            value: AtomicU64::new(value),
+            updated: AtomicI64::new(now()),
+        }
+    }
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Keeps counters monotonic across restarts by carrying their totals in the state snapshot.
+///
+/// Counters are exported as the running total rather than incremented in place, so a
+/// series that went idle and was dropped by the exporter also comes back where it left off.
+pub struct PersistCountersLayer;
+
+impl<R> Layer<R> for PersistCountersLayer {
+    type Output = PersistCounters<R>;
+
+    fn layer(&self, inner: R) -> Self::Output {
+        let restored: HashMap<String, u64> = state::get(STATE_KEY).unwrap_or_default();
+        TOTALS.lock().unwrap().extend(
+            restored
+                .into_iter()
+                .map(|(id, total)| (id, Arc::new(Total::new(total)))),
+        );
+
+        state::before_save(persist);
+
+        PersistCounters { inner }
+    }
+}
+
+pub struct PersistCounters<R> {
+    inner: R,
+}
+
+struct PersistentCounter {
+    inner: Counter,
+    total: Arc<Total>,
+}
+
+impl CounterFn for PersistentCounter {
+    fn increment(&self, value: u64) {
+        let total = self.total.value.fetch_add(value, Ordering::Relaxed) + value;
+        self.total.updated.store(now(), Ordering::Relaxed);
+        self.inner.absolute(total);
+    }
+
+    fn absolute(&self, value: u64) {
+        let total = self
+            .total
+            .value
+            .fetch_max(value, Ordering::Relaxed)
+            .max(value);
+        self.total.updated.store(now(), Ordering::Relaxed);
+        self.inner.absolute(total);
+    }
+}
+
+/// Identifies a series independently of the order its labels were given in
+fn series_id(key: &Key) -> String {
+    let mut labels: Vec<String> = key
+        .labels()
+        .map(|l| format!("{}={:?}", l.key(), l.value()))
+        .collect();
+    labels.sort();
+
+    format!("{}{{{}}}", key.name(), labels.join(","))
+}
+
+/// Forgets the totals updated least recently once there are more than `max`
+fn prune(totals: &mut HashMap<String, Arc<Total>>, max: usize) {
+    if totals.len() <= max {
+        return;
+    }
+
+    let mut by_age: Vec<(i64, String)> = totals
+        .iter()
+        .map(|(id, total)| (total.updated.load(Ordering::Relaxed), id.clone()))
+        .collect();
+    by_age.sort_unstable();
+
+    let excess = totals.len() - max;
+    for (_, id) in by_age.into_iter().take(excess) {
+        totals.remove(&id);
+    }
+}
+
+/// Copies the counter totals into the state snapshot
+fn persist() {
+    let mut totals = TOTALS.lock().unwrap();
+    prune(&mut totals, MAX_SERIES);
+
+    let snapshot: HashMap<String, u64> = totals
+        .iter()
+        .map(|(id, total)| (id.clone(), total.value.load(Ordering::Relaxed)))
+        .collect();
+    drop(totals);
+
+    state::put(STATE_KEY, &snapshot);
+}
+
+impl<R: Recorder> Recorder for PersistCounters<R> {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner.describe_counter(key, unit, description)
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner.describe_gauge(key, unit, description)
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner.describe_histogram(key, unit, description)
+    }
+
+    fn register_counter(&self, key: &Key, metadata: &Metadata<'_>) -> Counter {
+        let inner = self.inner.register_counter(key, metadata);
+
+        if !state::enabled() {
+            return inner;
+        }
+
+        let total = TOTALS
+            .lock()
+            .unwrap()
+            .entry(series_id(key))
+            .or_default()
+            .clone();
+
+        Counter::from_arc(Arc::new(PersistentCounter { inner, total }))
+    }
+
+    fn register_gauge(&self, key: &Key, metadata: &Metadata<'_>) -> Gauge {
+        self.inner.register_gauge(key, metadata)
+    }
+
+    fn register_histogram(&self, key: &Key, metadata: &Metadata<'_>) -> Histogram {
+        self.inner.register_histogram(key, metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_least_recently_updated() {
+        let mut totals: HashMap<String, Arc<Total>> = (0..5)
+            .map(|i| {
+                let total = Total::new(i);
+                total.updated.store(100 + i as i64, Ordering::Relaxed);
+                (i.to_string(), Arc::new(total))
+            })
+            .collect();
+
+        prune(&mut totals, 5);
+        assert_eq!(totals.len(), 5);
+
+        prune(&mut totals, 3);
+        let mut kept: Vec<&str> = totals.keys().map(String::as_str).collect();
+        kept.sort();
+        assert_eq!(kept, ["2", "3", "4"]);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::collectors::ProbeOutcome;
+use crate::config::MetricConfig;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// How many events a slow sink may fall behind before it starts missing them
+const EVENT_BUFFER: usize = 4096;
+
+static EVENTS: LazyLock<broadcast::Sender<Arc<ProbeEvent>>> =
+    LazyLock::new(|| broadcast::channel(EVENT_BUFFER).0);
+
+/// The outcome of a single collector run, before it is reduced to metrics.
+///
+/// Sinks that need more than the Prometheus aggregates subscribe to these.
+#[derive(Serialize, Debug, Clone)]
+pub struct ProbeEvent {
+    pub check_id: String,
+    pub protocol: &'static str,
+    pub endpoint: String,
+    pub timestamp: DateTime<Utc>,
+    /// The full Bitping API response, absent if the request itself failed
+    pub response: Option<serde_json::Value>,
+    /// Why the request to the Bitping API failed
+    pub error: Option<String>,
+    /// How the collector judged the probe, absent if it could not tell
+    pub outcome: Option<ProbeOutcome>,
+}
+
+impl ProbeEvent {
+    fn new(protocol: &'static str, check: &MetricConfig) -> Self {
+        Self {
+            check_id: check.check_id().to_string(),
+            protocol,
+            endpoint: check.endpoint_label().to_string(),
+            timestamp: Utc::now(),
+            response: None,
+            error: None,
+            outcome: None,
+        }
+    }
+
+    /// The node the probe ran on, as reported by the Bitping API
+    pub fn node_info(&self) -> Option<&serde_json::Value> {
+        self.response.as_ref()?.get("nodeInfo")
+    }
+
+    /// Collectors only request a single node, so only the first result is relevant
+    pub fn first_result(&self) -> Option<&serde_json::Value> {
+        self.response.as_ref()?.get("results")?.get(0)
+    }
+
+    pub fn duration_ms(&self) -> Option<f64> {
+        self.first_result()?.get("duration")?.as_f64()
+    }
+}
+
+pub fn subscribe() -> broadcast::Receiver<Arc<ProbeEvent>> {
+    EVENTS.subscribe()
+}
+
+/// Serializes a response for a later [`publish_response`], skipped when nothing is subscribed
+pub fn capture<T: Serialize>(response: &T) -> Option<serde_json::Value> {
+    if EVENTS.receiver_count() == 0 {
+        return None;
+    }
+
+    serde_json::to_value(response)
+        .inspect_err(|e| warn!(%e, "Failed to serialize response for probe event"))
+        .ok()
+}
+
+/// Publishes a response received from the Bitping API along with its outcome
+pub fn publish_response(
+    protocol: &'static str,
+    check: &MetricConfig,
+    response: Option<serde_json::Value>,
+    outcome: Option<ProbeOutcome>,
+) {
+    if EVENTS.receiver_count() == 0 {
+        return;
+    }
+
+    let _ = EVENTS.send(Arc::new(ProbeEvent {
+        response,
+        outcome,
+        ..ProbeEvent::new(protocol, check)
+    }));
+}
+
+/// Publishes a failed or timed out request to the Bitping API
+pub fn publish_error(protocol: &'static str, check: &MetricConfig, error: String) {
+    if EVENTS.receiver_count() == 0 {
+        return;
+    }
+
+    let _ = EVENTS.send(Arc::new(ProbeEvent {
+        error: Some(error),
+        ..ProbeEvent::new(protocol, check)
+    }));
+}
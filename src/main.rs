@@ -3,21 +3,35 @@ use collectors::http::HttpCollector;
 use collectors::icmp::IcmpCollector;
 use collectors::{dns, hls, Collector};
 use color_eyre::eyre::Result;
-use config::MetricType;
-use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use config::{HistogramConfig, MetricType};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use metrics_util::layers::Stack;
 use metrics_util::MetricKindMask;
 use poem::middleware::AddData;
 use poem::web::Data;
 use poem::EndpointExt;
 use poem::{get, handler, listener::TcpListener, Route, Server};
 use progenitor::generate_api;
+use std::num::NonZeroU32;
 use std::sync::LazyLock;
 use tokio::join;
+use tokio::sync::watch;
 use tokio::task::JoinSet;
 use tracing::{error, info};
 
+mod asn;
+mod assertions;
 mod collectors;
 mod config;
+mod counters;
+mod events;
+mod pushgateway;
+mod quorum;
+mod relabel;
+mod sinks;
+mod slo;
+mod state;
+mod template;
 
 generate_api!(spec = "./api-spec.json", interface = Builder);
 
@@ -66,43 +80,155 @@ fn render_prom(state: Data<&PrometheusHandle>) -> String {
     state.render()
 }
 
+/// Families with buckets are rendered as histograms, the rest as summaries
+fn configure_histograms(
+    mut builder: PrometheusBuilder,
+    config: &HistogramConfig,
+) -> Result<PrometheusBuilder> {
+    if let Some(buckets) = &config.buckets {
+        builder = builder.set_buckets(&buckets.bounds())?;
+    }
+
+    for (family, buckets) in &config.families {
+        builder =
+            builder.set_buckets_for_metric(Matcher::Suffix(family.clone()), &buckets.bounds())?;
+    }
+
+    if let Some(quantiles) = &config.quantiles {
+        builder = builder.set_quantiles(quantiles)?;
+    }
+
+    if let Some(age_buckets) = config.summary_age_buckets {
+        builder = builder.set_bucket_count(age_buckets);
+    }
+
+    if let Some(window) = config.summary_window {
+        // The exporter rolls summaries over 3 buckets by default
+        let age_buckets = config.summary_age_buckets.map_or(3, NonZeroU32::get);
+        builder = builder.set_bucket_duration(window / age_buckets)?;
+    }
+
+    Ok(builder)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     setup().await?;
 
     info!("Starting DNS metrics collector");
 
-    let handle = PrometheusBuilder::new()
+    let builder = PrometheusBuilder::new()
         .idle_timeout(
             MetricKindMask::COUNTER | MetricKindMask::HISTOGRAM | MetricKindMask::GAUGE,
             Some(CONFIG.global_config.metric_clear_timeout),
         )
-        .upkeep_timeout(CONFIG.global_config.metric_clear_timeout.saturating_mul(2))
-        .install_recorder()
+        .upkeep_timeout(CONFIG.global_config.metric_clear_timeout.saturating_mul(2));
+    let recorder =
+        configure_histograms(builder, &CONFIG.global_config.histograms)?.build_recorder();
+    let handle = recorder.handle();
+
+    if let Some(asn_config) = &CONFIG.global_config.asn_database {
+        asn::open(asn_config)?;
+    }
+
+    // Counters are restored from the state snapshot when they are first registered
+    if let Some(state_config) = &CONFIG.global_config.state {
+        state::restore(state_config)?;
+    }
+
+    // Relabel rules apply to every series before it reaches the exporter
+    Stack::new(recorder)
+        .push(counters::PersistCountersLayer)
+        .push(relabel::RelabelLayer::new(&CONFIG))
+        .install()
         .expect("failed to install recorder");
 
+    if let Some(pushgateway_config) = &CONFIG.global_config.pushgateway {
+        pushgateway::install(pushgateway_config, handle.clone());
+    }
+
     let app = Route::new()
         .at("/metrics", get(render_prom))
+        .at("/api/dns/changes", get(dns::changes::recent_changes))
+        .at(
+            "/api/dns/suspicious",
+            get(dns::consensus::suspicious_answers),
+        )
         .with(AddData::new(handle));
 
     let http_server = Server::new(TcpListener::bind("[::]:3000")).run(app);
 
+    // Sinks and trackers subscribe to probe events, so they start before the collectors
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut subscriber_set = JoinSet::new();
+
+    sinks::spawn_sinks(&CONFIG, &mut subscriber_set, shutdown_rx.clone())?;
+    slo::spawn_trackers(&CONFIG, &mut subscriber_set, shutdown_rx.clone());
+    quorum::spawn_trackers(&CONFIG, &mut subscriber_set, shutdown_rx.clone());
+
+    if let Some(providers_config) = &CONFIG.global_config.dns_providers {
+        dns::providers::load(providers_config)?;
+        subscriber_set.spawn(dns::providers::run(providers_config, shutdown_rx.clone()));
+    }
+
+    if let Some(state_config) = &CONFIG.global_config.state {
+        subscriber_set.spawn(state::run(state_config, shutdown_rx));
+    }
+
     // Start collection tasks
     let mut join_set = JoinSet::new();
 
     spawn_collectors(&CONFIG, &mut join_set).await?;
 
-    let (rs, _) = join!(http_server, join_set.join_all());
+    // A failed HTTP server still gets the final push and state save before the error is returned
+    let result = tokio::select! {
+        (rs, _) = async { join!(http_server, join_set.join_all()) } => rs,
+        _ = shutdown_signal() => {
+            info!("Shutting down");
+            Ok(())
+        }
+    };
 
-    rs?;
+    pushgateway::shutdown(&CONFIG).await;
 
-    Ok(())
+    let _ = shutdown_tx.send(true);
+    subscriber_set.join_all().await;
+
+    if let Err(e) = state::save() {
+        error!(%e, "Failed to save state");
+    }
+
+    Ok(result?)
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = tokio::signal::ctrl_c();
+
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("failed to install SIGTERM handler");
+
+        tokio::select! {
+            _ = ctrl_c => {},
+            _ = terminate.recv() => {},
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = ctrl_c.await;
+    }
 }
 
 async fn spawn_collectors(config: &'static Conf, join_set: &mut JoinSet<()>) -> Result<()> {
     for metric in &config.metrics {
         match metric {
             MetricType::Dns(config) => {
+                // Unknown servers or presets are rejected rather than skipped on every run
+                dns::resolve_dns_servers(&config.dns_servers)?;
+
                 join_set.spawn(async move {
                     loop {
                         let collector = dns::DnsCollector::new(config);
@@ -0,0 +1,318 @@
+use crate::config::{Conf, MetricConfig, PushTrigger, PushgatewayConfig};
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use color_eyre::eyre::Result;
+use metrics::Key;
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::collections::{HashMap, HashSet};
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+static PUSHGATEWAY: OnceLock<Pushgateway> = OnceLock::new();
+
+/// Series each check emitted, keyed by check id, as the exporter receives them after
+/// relabeling. Pushes select samples by these rather than by their labels, which rules
+/// may have renamed or dropped.
+static SERIES: LazyLock<Mutex<HashMap<String, HashSet<Series>>>> = LazyLock::new(Default::default);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Metric name and labels sorted by name
+type Series = (String, Vec<(String, String)>);
+
+/// Pushes the series of each check to a Prometheus Pushgateway, grouped by job and check id.
+///
+/// Used when the exporter runs as a batch job and there is no long lived scrape target.
+pub struct Pushgateway {
+    config: &'static PushgatewayConfig,
+    handle: PrometheusHandle,
+    client: reqwest::Client,
+}
+
+/// Installs the global Pushgateway client. Without it, pushing is a no-op.
+pub fn install(config: &'static PushgatewayConfig, handle: PrometheusHandle) {
+    // Pushes are awaited by the collectors, a hung Pushgateway must not stall them
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("failed to build Pushgateway client");
+
+    let pushgateway = Pushgateway {
+        config,
+        handle,
+        client,
+    };
+
+    if PUSHGATEWAY.set(pushgateway).is_err() {
+        warn!("Pushgateway was already installed");
+    }
+}
+
+/// Whether series need to be tracked per check at all
+pub fn enabled() -> bool {
+    PUSHGATEWAY.get().is_some()
+}
+
+/// Remembers that a series belongs to a check
+pub fn track(check_id: &str, key: &Key) {
+    let mut labels: Vec<(String, String)> = key
+        .labels()
+        .map(|l| (l.key().to_string(), l.value().to_string()))
+        .collect();
+    labels.sort();
+
+    SERIES
+        .lock()
+        .unwrap()
+        .entry(check_id.to_string())
+        .or_default()
+        .insert((key.name().to_string(), labels));
+}
+
+/// Called by every collector after a run completes
+pub async fn after_run(check: &MetricConfig) {
+    let Some(pushgateway) = PUSHGATEWAY.get() else {
+        return;
+    };
+
+    if pushgateway.config.push_on != PushTrigger::EachRun {
+        return;
+    }
+
+    if let Err(e) = pushgateway.push(check).await {
+        error!(%e, check = check.check_id(), "Failed to push metrics to Pushgateway");
+    }
+}
+
+/// Performs the final push and, when configured, deletes every check's grouping
+pub async fn shutdown(config: &Conf) {
+    let Some(pushgateway) = PUSHGATEWAY.get() else {
+        return;
+    };
+
+    for metric in &config.metrics {
+        let check = metric.common_config();
+
+        if pushgateway.config.push_on == PushTrigger::Exit {
+            if let Err(e) = pushgateway.push(check).await {
+                error!(%e, check = check.check_id(), "Failed to push metrics to Pushgateway");
+            }
+        }
+
+        if pushgateway.config.delete_on_shutdown {
+            if let Err(e) = pushgateway.delete(check).await {
+                error!(%e, check = check.check_id(), "Failed to delete Pushgateway grouping");
+            }
+        }
+    }
+}
+
+impl Pushgateway {
+    async fn push(&self, check: &MetricConfig) -> Result<()> {
+        let body = {
+            let mut series = SERIES.lock().unwrap();
+            let Some(tracked) = series.get_mut(check.check_id()) else {
+                return Ok(());
+            };
+
+            // Series the exporter expired are no longer rendered and are forgotten too
+            let (body, rendered) = filter_exposition(&self.handle.render(), tracked);
+            *tracked = rendered;
+            body
+        };
+
+        if body.is_empty() {
+            return Ok(());
+        }
+
+        self.client
+            .put(self.grouping_url(check))
+            .header("content-type", "text/plain; version=0.0.4")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, check: &MetricConfig) -> Result<()> {
+        self.client
+            .delete(self.grouping_url(check))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        info!(check = check.check_id(), "Deleted Pushgateway grouping");
+        Ok(())
+    }
+
+    /// Check ids are frequently URLs, so the grouping value is always base64 encoded
+    fn grouping_url(&self, check: &MetricConfig) -> String {
+        format!(
+            "{}/metrics/job@base64/{}/check@base64/{}",
+            self.config.url.trim_end_matches('/'),
+            URL_SAFE.encode(&self.config.job),
+            URL_SAFE.encode(check.check_id()),
+        )
+    }
+}
+
+/// Keeps only the samples in a rendered exposition that belong to the tracked series,
+/// along with the `# HELP`/`# TYPE` lines of the families they belong to. Also returns
+/// the tracked series that were found.
+fn filter_exposition(rendered: &str, tracked: &HashSet<Series>) -> (String, HashSet<Series>) {
+    let mut output = String::new();
+    let mut found = HashSet::new();
+    let mut headers: Vec<&str> = Vec::new();
+    let mut in_headers = false;
+    let mut headers_written = false;
+
+    for line in rendered.lines() {
+        if line.starts_with('#') {
+            // A new family begins with its HELP/TYPE lines
+            if !in_headers {
+                headers.clear();
+                headers_written = false;
+            }
+            in_headers = true;
+            headers.push(line);
+            continue;
+        }
+        in_headers = false;
+
+        let Some(series) = parse_sample(line).and_then(|sample| tracked_series(sample, tracked))
+        else {
+            continue;
+        };
+        found.insert(series);
+
+        if !headers_written {
+            for header in &headers {
+                output.push_str(header);
+                output.push('\n');
+            }
+            headers_written = true;
+        }
+
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    (output, found)
+}
+
+/// The tracked series a sample was rendered from. Histograms and summaries render a
+/// series as several samples, with a suffix and an `le` or `quantile` label.
+fn tracked_series(sample: Series, tracked: &HashSet<Series>) -> Option<Series> {
+    if tracked.contains(&sample) {
+        return Some(sample);
+    }
+
+    let (name, mut labels) = sample;
+    labels.retain(|(k, _)| k != "le" && k != "quantile");
+
+    ["_bucket", "_sum", "_count", ""]
+        .iter()
+        .filter_map(|suffix| name.strip_suffix(suffix))
+        .map(|name| (name.to_string(), labels.clone()))
+        .find(|series| tracked.contains(series))
+}
+
+/// Splits a sample line, `name{label="value",...} value`, into its name and labels
+fn parse_sample(line: &str) -> Option<Series> {
+    let name_end = line.find(['{', ' '])?;
+    let name = line[..name_end].to_string();
+    let mut labels = Vec::new();
+
+    let mut chars = line[name_end..].chars().peekable();
+    if chars.peek() == Some(&'{') {
+        chars.next();
+
+        loop {
+            let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+            let key = key.trim_start_matches(',').trim().to_string();
+            if key.is_empty() || key.starts_with('}') {
+                break;
+            }
+            if chars.next() != Some('"') {
+                return None;
+            }
+
+            let mut value = String::new();
+            loop {
+                match chars.next()? {
+                    '\\' => match chars.next()? {
+                        'n' => value.push('\n'),
+                        escaped => value.push(escaped),
+                    },
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+            labels.push((key, value));
+
+            if chars.peek() == Some(&'}') {
+                break;
+            }
+        }
+    }
+
+    labels.sort();
+    Some((name, labels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(name: &str, labels: &[(&str, &str)]) -> Series {
+        let mut labels: Vec<(String, String)> = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        labels.sort();
+        (name.to_string(), labels)
+    }
+
+    #[test]
+    fn parses_escaped_labels() {
+        assert_eq!(
+            parse_sample(r#"up{b="x\"y",a="1\\2\n"} 1"#),
+            Some(series("up", &[("a", "1\\2\n"), ("b", "x\"y")]))
+        );
+        assert_eq!(parse_sample("up 1"), Some(series("up", &[])));
+        assert_eq!(parse_sample("up{} 1"), Some(series("up", &[])));
+    }
+
+    #[test]
+    fn keeps_tracked_series_only() {
+        let rendered = "\
+# HELP check_success Whether the check passed
+# TYPE check_success gauge
+check_success{check=\"a\"} 1
+check_success{check=\"b\"} 0
+# TYPE http_duration histogram
+http_duration_bucket{endpoint=\"x\",le=\"10\"} 1
+http_duration_sum{endpoint=\"x\"} 4
+http_duration_count{endpoint=\"x\"} 1
+";
+        let tracked = HashSet::from([
+            series("check_success", &[("check", "a")]),
+            series("http_duration", &[("endpoint", "x")]),
+            series("expired", &[]),
+        ]);
+
+        let (body, found) = filter_exposition(rendered, &tracked);
+
+        assert!(body.contains("check_success{check=\"a\"} 1"));
+        assert!(!body.contains("check=\"b\""));
+        assert!(body.contains("# TYPE http_duration histogram"));
+        assert_eq!(body.lines().count(), 7);
+        assert_eq!(found.len(), 2);
+        assert!(!found.contains(&series("expired", &[])));
+    }
+}
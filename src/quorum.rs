@@ -0,0 +1,228 @@
+use crate::collectors::ProbeOutcome;
+use crate::config::{Conf, MetricConfig, QuorumConfig, QuorumLocation};
+use crate::events::{self, ProbeEvent};
+use geohash::Coord;
+use metrics::gauge;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+/// Last result seen from a single location
+struct LocationResult {
+    timestamp: i64,
+    failed: bool,
+}
+
+struct TrackedCheck {
+    check: &'static MetricConfig,
+    quorum: &'static QuorumConfig,
+    locations: HashMap<String, LocationResult>,
+    up: bool,
+}
+
+/// Decides whether an endpoint is really down from the latest results of distinct
+/// vantage points, so a single failing node does not flip the check.
+pub struct QuorumTracker {
+    checks: HashMap<String, TrackedCheck>,
+    refresh_interval: Duration,
+}
+
+pub fn spawn_trackers(
+    config: &'static Conf,
+    join_set: &mut JoinSet<()>,
+    shutdown: watch::Receiver<bool>,
+) {
+    let checks: HashMap<String, TrackedCheck> = config
+        .metrics
+        .iter()
+        .map(|metric| metric.common_config())
+        .filter_map(|check| {
+            let quorum = check.quorum.as_ref()?;
+
+            Some((
+                check.check_id().to_string(),
+                TrackedCheck {
+                    check,
+                    quorum,
+                    locations: HashMap::new(),
+                    up: true,
+                },
+            ))
+        })
+        .collect();
+
+    if checks.is_empty() {
+        return;
+    }
+
+    // Gauges not updated within the clear timeout are dropped from the exporter
+    let refresh_interval =
+        (config.global_config.metric_clear_timeout / 2).max(Duration::from_secs(1));
+
+    let tracker = QuorumTracker {
+        checks,
+        refresh_interval,
+    };
+    join_set.spawn(tracker.run(events::subscribe(), shutdown));
+}
+
+impl QuorumTracker {
+    async fn run(
+        mut self,
+        mut events: broadcast::Receiver<Arc<ProbeEvent>>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        info!(checks = self.checks.len(), "Evaluating location quorums");
+        self.describe();
+
+        let mut refresh = tokio::time::interval(self.refresh_interval);
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => self.observe(&event),
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(missed, "Quorum tracker fell behind, probe outcomes were dropped");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = refresh.tick() => self.refresh(),
+                _ = shutdown.changed() => break,
+            }
+        }
+    }
+
+    fn describe(&self) {
+        for tracked in self.checks.values() {
+            let prefix = &tracked.check.prefix;
+
+            metrics::describe_gauge!(
+                format!("{}quorum_up", prefix),
+                "Whether fewer locations than the quorum are failing"
+            );
+            metrics::describe_gauge!(
+                format!("{}quorum_locations", prefix),
+                "Number of locations the quorum was evaluated over"
+            );
+            metrics::describe_gauge!(
+                format!("{}quorum_failing_locations", prefix),
+                "Number of locations whose last probe failed"
+            );
+            metrics::describe_gauge!(
+                format!("{}quorum_location_failing", prefix),
+                "Whether the last probe from the location failed"
+            );
+        }
+    }
+
+    fn observe(&mut self, event: &ProbeEvent) {
+        let Some(tracked) = self.checks.get_mut(&event.check_id) else {
+            return;
+        };
+
+        // Failed requests to the Bitping API say nothing about the endpoint itself
+        let Some(outcome) = event.outcome else {
+            return;
+        };
+
+        let Some(location) = location(event, tracked.quorum.by) else {
+            return;
+        };
+
+        let now = event.timestamp.timestamp();
+        tracked.locations.insert(
+            location,
+            LocationResult {
+                timestamp: now,
+                failed: outcome == ProbeOutcome::Failure,
+            },
+        );
+
+        tracked.refresh(now);
+    }
+
+    fn refresh(&mut self) {
+        let now = chrono::Utc::now().timestamp();
+
+        for tracked in self.checks.values_mut() {
+            tracked.refresh(now);
+        }
+    }
+}
+
+/// Location of the node that ran the probe, derived from the same `nodeInfo`
+/// fields the collectors label their series with
+fn location(event: &ProbeEvent, by: QuorumLocation) -> Option<String> {
+    let node_info = event.node_info()?;
+    let field = |name: &str| node_info.get(name)?.as_str().map(str::to_string);
+
+    match by {
+        QuorumLocation::Country => field("countryCode"),
+        QuorumLocation::Isp => field("isp"),
+        QuorumLocation::Geohash => {
+            let coord = Coord {
+                x: node_info.get("lon")?.as_f64()?,
+                y: node_info.get("lat")?.as_f64()?,
+            };
+            geohash::encode(coord, 5).ok()
+        }
+    }
+}
+
+impl TrackedCheck {
+    fn refresh(&mut self, now: i64) {
+        let window_secs = self.quorum.window.as_secs() as i64;
+        self.locations
+            .retain(|_, result| result.timestamp > now - window_secs);
+
+        // Only the most recently probed locations take part in the vote
+        let mut recent: Vec<(&String, &LocationResult)> = self.locations.iter().collect();
+        recent.sort_by_key(|(_, result)| std::cmp::Reverse(result.timestamp));
+        recent.truncate(self.quorum.locations);
+
+        let failing = recent.iter().filter(|(_, result)| result.failed).count();
+        let up = failing < self.quorum.failures;
+
+        let prefix = &self.check.prefix;
+        let check_id = self.check.check_id();
+
+        for (location, result) in &recent {
+            gauge!(
+                format!("{}quorum_location_failing", prefix),
+                "check" => check_id.to_string(),
+                "location" => location.to_string()
+            )
+            .set(result.failed as u8 as f64);
+        }
+
+        gauge!(format!("{}quorum_locations", prefix), "check" => check_id.to_string())
+            .set(recent.len() as f64);
+        gauge!(format!("{}quorum_failing_locations", prefix), "check" => check_id.to_string())
+            .set(failing as f64);
+        gauge!(format!("{}quorum_up", prefix), "check" => check_id.to_string())
+            .set(up as u8 as f64);
+
+        if up != self.up {
+            let failing_locations: Vec<&str> = recent
+                .iter()
+                .filter(|(_, result)| result.failed)
+                .map(|(location, _)| location.as_str())
+                .collect();
+
+            if up {
+                info!(check = check_id, "Quorum recovered");
+            } else {
+                warn!(
+                    check = check_id,
+                    ?failing_locations,
+                    "Quorum of locations failing"
+                );
+            }
+            self.up = up;
+        }
+    }
+}
@@ -0,0 +1,509 @@
+use crate::config::{Conf, MetricConfig, MetricType, RelabelAction, RelabelConfig};
+use crate::pushgateway;
+use crate::template;
+use metrics::{
+    Counter, Gauge, Histogram, Key, KeyName, Label, Metadata, Recorder, SharedString, Unit,
+};
+use metrics_util::layers::Layer;
+use regex::Regex;
+
+/// Pseudo-label holding the metric name while rules are applied
+const NAME_LABEL: &str = "__name__";
+
+/// A relabel regex, anchored at both ends like Prometheus does
+#[derive(Debug, Clone)]
+pub struct RelabelRegex(Regex);
+
+impl Default for RelabelRegex {
+    fn default() -> Self {
+        Self(Regex::new("^(?:(.*))$").unwrap())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for RelabelRegex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&format!("^(?:{pattern})$"))
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Series owned by a check: the ones labelled with its `check` id, or with its
+/// `endpoint` label under its protocol's metric family
+#[derive(Clone)]
+struct CheckRules {
+    check: &'static MetricConfig,
+    family_prefix: String,
+    /// Custom labels with their templates already rendered
+    labels: Vec<(String, String)>,
+}
+
+/// Wraps the exporter's recorder so custom labels and relabel rules apply to every
+/// series centrally, whichever collector, tracker or sink emitted it.
+#[derive(Clone)]
+pub struct RelabelLayer {
+    global: &'static [RelabelConfig],
+    /// Every check, also the ones without labels or rules, so series can be attributed
+    checks: Vec<CheckRules>,
+    empty: bool,
+}
+
+impl RelabelLayer {
+    pub fn new(config: &'static Conf) -> Self {
+        let checks = config
+            .metrics
+            .iter()
+            .map(|metric| {
+                let check = metric.common_config();
+                CheckRules {
+                    check,
+                    family_prefix: format!("{}{}_", check.prefix, metric.protocol()),
+                    labels: custom_labels(config, metric),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let global = &config.global_config.relabel_configs;
+        let empty = global.is_empty()
+            && checks
+                .iter()
+                .all(|rules| rules.check.relabel_configs.is_empty() && rules.labels.is_empty());
+
+        Self {
+            global,
+            checks,
+            empty,
+        }
+    }
+
+    /// Whether any labels or rules are configured at all, so the layer can be skipped otherwise
+    pub fn is_empty(&self) -> bool {
+        self.empty
+    }
+
+    fn check_rules(&self, name: &str, labels: &[(String, String)]) -> Option<&CheckRules> {
+        let label = |key: &str| labels.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+
+        self.checks.iter().find(|rules| {
+            label("check").is_some_and(|c| c == rules.check.check_id())
+                || (name.starts_with(&rules.family_prefix)
+                    && label("endpoint").is_some_and(|e| e == rules.check.endpoint_label()))
+        })
+    }
+
+    /// Adds the check's custom labels, then applies its rules and the global ones.
+    /// Returns `None` if the series is dropped.
+    fn relabel(&self, key: &Key) -> Option<Key> {
+        let relabelled = self.apply_rules(key)?;
+
+        // The Pushgateway pushes each check's series on their own, rules may have renamed
+        // or dropped the labels that tell which check a series belongs to
+        if pushgateway::enabled() {
+            let labels: Vec<(String, String)> = key
+                .labels()
+                .map(|l| (l.key().to_string(), l.value().to_string()))
+                .collect();
+            if let Some(rules) = self.check_rules(key.name(), &labels) {
+                pushgateway::track(rules.check.check_id(), &relabelled);
+            }
+        }
+
+        Some(relabelled)
+    }
+
+    fn apply_rules(&self, key: &Key) -> Option<Key> {
+        if self.is_empty() {
+            return Some(key.clone());
+        }
+
+        let mut labels: Vec<(String, String)> = key
+            .labels()
+            .map(|l| (l.key().to_string(), l.value().to_string()))
+            .collect();
+
+        let check = self.check_rules(key.name(), &labels);
+        let check_rules = check.map_or(&[][..], |c| c.check.relabel_configs.as_slice());
+
+        // Labels emitted by the collectors take precedence over custom ones
+        for (name, value) in check.iter().flat_map(|c| &c.labels) {
+            if !labels.iter().any(|(k, _)| k == name) {
+                labels.push((name.clone(), value.clone()));
+            }
+        }
+
+        labels.push((NAME_LABEL.to_string(), key.name().to_string()));
+
+        for rule in check_rules.iter().chain(self.global) {
+            if !apply(rule, &mut labels) {
+                return None;
+            }
+        }
+
+        let name = labels
+            .iter()
+            .find(|(k, _)| k == NAME_LABEL)
+            .map(|(_, v)| v.clone())
+            .filter(|name| !name.is_empty())?;
+
+        // Labels starting with `__` are only visible to the rules, empty labels are unset
+        let labels: Vec<Label> = labels
+            .into_iter()
+            .filter(|(k, v)| !k.starts_with("__") && !v.is_empty())
+            .map(|(k, v)| Label::new(k, v))
+            .collect();
+
+        Some(Key::from_parts(name, labels))
+    }
+}
+
+/// Global default labels merged with the check's own, with placeholders rendered
+fn custom_labels(config: &Conf, metric: &MetricType) -> Vec<(String, String)> {
+    let check = metric.common_config();
+    let mut labels = config.global_config.labels.clone();
+    labels.extend(check.labels.clone());
+
+    labels
+        .into_iter()
+        .map(|(name, value)| {
+            let value = template::render(
+                &value,
+                |field| match field {
+                    "check" => Some(check.check_id().to_string()),
+                    "name" => check.name.clone(),
+                    "endpoint" => Some(check.endpoint.clone()),
+                    "endpoint_host" => Some(check.endpoint_host()),
+                    "protocol" => Some(metric.protocol().to_string()),
+                    "prefix" => Some(check.prefix.clone()),
+                    _ => None,
+                },
+                str::to_string,
+            );
+            (name, value)
+        })
+        .collect()
+}
+
+/// Applies a single rule in place, returns `false` if the series should be dropped
+fn apply(rule: &RelabelConfig, labels: &mut Vec<(String, String)>) -> bool {
+    let regex = &rule.regex.0;
+    let source = || {
+        rule.source_labels
+            .iter()
+            .map(|name| {
+                labels
+                    .iter()
+                    .find(|(k, _)| k == name)
+                    .map(|(_, v)| v.as_str())
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>()
+            .join(&rule.separator)
+    };
+
+    match rule.action {
+        RelabelAction::Keep => regex.is_match(&source()),
+        RelabelAction::Drop => !regex.is_match(&source()),
+        RelabelAction::Replace => {
+            let value = source();
+            if let (Some(captures), Some(target)) = (regex.captures(&value), &rule.target_label) {
+                let mut replaced = String::new();
+                captures.expand(&rule.replacement, &mut replaced);
+                set_label(labels, target, replaced);
+            }
+            true
+        }
+        RelabelAction::Hashmod => {
+            if let (Some(modulus), Some(target)) = (rule.modulus, &rule.target_label) {
+                let hash = fnv1a(source().as_bytes()) % modulus.max(1);
+                set_label(labels, target, hash.to_string());
+            }
+            true
+        }
+        RelabelAction::Labelmap => {
+            let mapped: Vec<(String, String)> = labels
+                .iter()
+                .filter_map(|(k, v)| {
+                    let captures = regex.captures(k)?;
+                    let mut name = String::new();
+                    captures.expand(&rule.replacement, &mut name);
+                    Some((name, v.clone()))
+                })
+                .collect();
+
+            for (name, value) in mapped {
+                set_label(labels, &name, value);
+            }
+            true
+        }
+        RelabelAction::Labeldrop => {
+            labels.retain(|(k, _)| k == NAME_LABEL || !regex.is_match(k));
+            true
+        }
+        RelabelAction::Labelkeep => {
+            labels.retain(|(k, _)| k == NAME_LABEL || regex.is_match(k));
+            true
+        }
+    }
+}
+
+fn set_label(labels: &mut Vec<(String, String)>, name: &str, value: String) {
+    match labels.iter_mut().find(|(k, _)| k == name) {
+        Some((_, existing)) => *existing = value,
+        None => labels.push((name.to_string(), value)),
+    }
+}
+
+/// Stable across builds and platforms, so `hashmod` shards agree between instances
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl<R> Layer<R> for RelabelLayer {
+    type Output = Relabel<R>;
+
+    fn layer(&self, inner: R) -> Self::Output {
+        Relabel {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+pub struct Relabel<R> {
+    inner: R,
+    layer: RelabelLayer,
+}
+
+impl<R: Recorder> Relabel<R> {
+    /// Descriptions follow renames made by rules that only look at the metric name
+    fn describe_name(&self, key: KeyName) -> KeyName {
+        match self.layer.apply_rules(&Key::from_name(key.clone())) {
+            Some(relabelled) => relabelled.into_parts().0,
+            None => key,
+        }
+    }
+}
+
+impl<R: Recorder> Recorder for Relabel<R> {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner
+            .describe_counter(self.describe_name(key), unit, description)
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner
+            .describe_gauge(self.describe_name(key), unit, description)
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner
+            .describe_histogram(self.describe_name(key), unit, description)
+    }
+
+    fn register_counter(&self, key: &Key, metadata: &Metadata<'_>) -> Counter {
+        match self.layer.relabel(key) {
+            Some(key) => self.inner.register_counter(&key, metadata),
+            None => Counter::noop(),
+        }
+    }
+
+    fn register_gauge(&self, key: &Key, metadata: &Metadata<'_>) -> Gauge {
+        match self.layer.relabel(key) {
+            Some(key) => self.inner.register_gauge(&key, metadata),
+            None => Gauge::noop(),
+        }
+    }
+
+    fn register_histogram(&self, key: &Key, metadata: &Metadata<'_>) -> Histogram {
+        match self.layer.relabel(key) {
+            Some(key) => self.inner.register_histogram(&key, metadata),
+            None => Histogram::noop(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use figment::providers::{Format, Yaml};
+    use figment::Figment;
+
+    fn rules(yaml: &str) -> Vec<RelabelConfig> {
+        Figment::from(Yaml::string(&format!("rules:{yaml}")))
+            .extract_inner("rules")
+            .unwrap()
+    }
+
+    fn layer(yaml: &str) -> RelabelLayer {
+        RelabelLayer {
+            global: Box::leak(rules(yaml).into_boxed_slice()),
+            checks: Vec::new(),
+            empty: false,
+        }
+    }
+
+    fn key(name: &str, labels: &[(&str, &str)]) -> Key {
+        let labels: Vec<Label> = labels
+            .iter()
+            .map(|(k, v)| Label::new(k.to_string(), v.to_string()))
+            .collect();
+        Key::from_parts(name.to_string(), labels)
+    }
+
+    fn label<'a>(key: &'a Key, name: &str) -> Option<&'a str> {
+        key.labels().find(|l| l.key() == name).map(|l| l.value())
+    }
+
+    #[test]
+    fn anchors_regexes() {
+        let layer = layer(
+            r#"
+- source_labels: [country_code]
+  regex: U
+  action: drop
+"#,
+        );
+
+        assert!(layer
+            .apply_rules(&key("up", &[("country_code", "US")]))
+            .is_some());
+        assert!(layer
+            .apply_rules(&key("up", &[("country_code", "U")]))
+            .is_none());
+    }
+
+    #[test]
+    fn keeps_and_drops() {
+        let layer = layer(
+            r#"
+- source_labels: [__name__, isp]
+  separator: "|"
+  regex: "http_.*\\|.*Telecom.*"
+  action: keep
+"#,
+        );
+
+        assert!(layer
+            .apply_rules(&key("http_up", &[("isp", "Big Telecom")]))
+            .is_some());
+        assert!(layer
+            .apply_rules(&key("http_up", &[("isp", "Cable Co")]))
+            .is_none());
+        assert!(layer
+            .apply_rules(&key("dns_up", &[("isp", "Big Telecom")]))
+            .is_none());
+    }
+
+    #[test]
+    fn replaces_labels_and_names() {
+        let layer = layer(
+            r#"
+- source_labels: [city, country_code]
+  regex: "(.+);(.+)"
+  target_label: location
+  replacement: "$2/$1"
+- source_labels: [__name__]
+  regex: "bitping_(.*)"
+  target_label: __name__
+- source_labels: [city]
+  target_label: city
+  replacement: ""
+"#,
+        );
+
+        let relabelled = layer
+            .apply_rules(&key(
+                "bitping_up",
+                &[("city", "Paris"), ("country_code", "FR")],
+            ))
+            .unwrap();
+
+        assert_eq!(relabelled.name(), "up");
+        assert_eq!(label(&relabelled, "location"), Some("FR/Paris"));
+        // Emptied labels are removed
+        assert_eq!(label(&relabelled, "city"), None);
+    }
+
+    #[test]
+    fn drops_series_without_a_name() {
+        let layer = layer(
+            r#"
+- target_label: __name__
+  replacement: ""
+"#,
+        );
+
+        assert!(layer.apply_rules(&key("up", &[])).is_none());
+    }
+
+    #[test]
+    fn maps_and_filters_label_names() {
+        let layer = layer(
+            r#"
+- regex: "node_(.+)"
+  action: labelmap
+- regex: "node_.*|os"
+  action: labeldrop
+"#,
+        );
+
+        let relabelled = layer
+            .apply_rules(&key(
+                "up",
+                &[("node_isp", "Cable Co"), ("os", "linux"), ("city", "Oslo")],
+            ))
+            .unwrap();
+        let mut names: Vec<&str> = relabelled.labels().map(|l| l.key()).collect();
+        names.sort();
+
+        assert_eq!(names, ["city", "isp"]);
+        assert_eq!(label(&relabelled, "isp"), Some("Cable Co"));
+    }
+
+    #[test]
+    fn labelkeep_keeps_the_name() {
+        let layer = layer(
+            r#"
+- regex: isp
+  action: labelkeep
+"#,
+        );
+
+        let relabelled = layer
+            .apply_rules(&key("up", &[("isp", "Cable Co"), ("city", "Oslo")]))
+            .unwrap();
+
+        assert_eq!(relabelled.name(), "up");
+        assert_eq!(relabelled.labels().count(), 1);
+    }
+
+    #[test]
+    fn hashmod_is_stable() {
+        let layer = layer(
+            r#"
+- source_labels: [endpoint]
+  target_label: shard
+  modulus: 4
+  action: hashmod
+"#,
+        );
+
+        let shard = |endpoint| {
+            let relabelled = layer
+                .apply_rules(&key("up", &[("endpoint", endpoint)]))
+                .unwrap();
+            label(&relabelled, "shard").unwrap().to_string()
+        };
+
+        assert_eq!(shard("example.com"), shard("example.com"));
+        assert_eq!(
+            shard("example.com"),
+            (fnv1a(b"example.com") % 4).to_string()
+        );
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+}
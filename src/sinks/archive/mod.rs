@@ -0,0 +1,300 @@
+#[cfg(feature = "parquet")]
+mod parquet;
+
+use crate::config::{ArchiveConfig, ArchiveFormat};
+use crate::events::ProbeEvent;
+use chrono::Utc;
+use color_eyre::eyre::{eyre, Result};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::{broadcast, watch};
+use tracing::{error, info, warn};
+
+const FILE_PREFIX: &str = "probes-";
+
+/// Writes every raw Bitping response, enriched with its check id and timestamp,
+/// to size or time rotated files so history can be queried offline.
+pub struct Archive {
+    config: &'static ArchiveConfig,
+    current: Option<ArchiveFile>,
+}
+
+struct ArchiveFile {
+    path: PathBuf,
+    opened_at: Instant,
+    writer: ArchiveWriter,
+}
+
+enum ArchiveWriter {
+    Jsonl {
+        writer: BufWriter<File>,
+        bytes: u64,
+    },
+    #[cfg(feature = "parquet")]
+    Parquet(Box<parquet::ParquetFile>),
+}
+
+impl Archive {
+    pub fn new(config: &'static ArchiveConfig) -> Result<Self> {
+        if config.format == ArchiveFormat::Parquet && !cfg!(feature = "parquet") {
+            return Err(eyre!(
+                "Archive format is parquet but this binary was built without the `parquet` feature"
+            ));
+        }
+
+        fs::create_dir_all(&config.path)?;
+
+        Ok(Self {
+            config,
+            current: None,
+        })
+    }
+
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<Arc<ProbeEvent>>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        info!(path = ?self.config.path, format = ?self.config.format, "Archiving probe results");
+
+        let mut flush_interval = tokio::time::interval(self.config.flush_interval);
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        // Take whatever else is queued, so a busy bus costs one blocking task
+                        let mut batch = vec![event];
+                        batch.extend(drain(&mut events));
+                        self = self.blocking(move |archive| archive.write_batch(&batch)).await;
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(missed, "Archive fell behind, probe results were dropped");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = flush_interval.tick() => {
+                    self = self.blocking(|archive| {
+                        if let Err(e) = archive.flush() {
+                            error!(%e, "Failed to flush archive file");
+                        }
+                    }).await;
+                }
+                _ = shutdown.changed() => break,
+            }
+        }
+
+        // Results published before shutdown are still archived
+        let batch = drain(&mut events);
+        self.blocking(move |archive| {
+            archive.write_batch(&batch);
+
+            if let Some(file) = archive.current.take() {
+                if let Err(e) = file.close() {
+                    error!(%e, "Failed to close archive file");
+                }
+            }
+        })
+        .await;
+    }
+
+    /// File I/O runs on the blocking pool rather than stalling the runtime's workers
+    async fn blocking(mut self, f: impl FnOnce(&mut Self) + Send + 'static) -> Self {
+        tokio::task::spawn_blocking(move || {
+            f(&mut self);
+            self
+        })
+        .await
+        .expect("archive writer panicked")
+    }
+
+    fn write_batch(&mut self, batch: &[Arc<ProbeEvent>]) {
+        for event in batch {
+            if let Err(e) = self.write(event) {
+                error!(%e, "Failed to archive probe result");
+            }
+        }
+    }
+
+    /// Parquet files are left alone, flushing them would write tiny row groups
+    fn flush(&mut self) -> Result<()> {
+        match self.current.as_mut().map(|file| &mut file.writer) {
+            Some(ArchiveWriter::Jsonl { writer, .. }) => Ok(writer.flush()?),
+            _ => Ok(()),
+        }
+    }
+
+    fn write(&mut self, event: &ProbeEvent) -> Result<()> {
+        // Only full responses are archived, failed requests carry no data
+        if event.response.is_none() {
+            return Ok(());
+        }
+
+        if self.current.as_ref().is_some_and(|f| self.should_rotate(f)) {
+            if let Some(file) = self.current.take() {
+                file.close()?;
+            }
+        }
+
+        let file = match &mut self.current {
+            Some(file) => file,
+            None => {
+                let file = ArchiveFile::create(self.config)?;
+                self.prune(&file.path);
+                self.current.insert(file)
+            }
+        };
+
+        file.writer.write(event)
+    }
+
+    fn should_rotate(&self, file: &ArchiveFile) -> bool {
+        file.writer.bytes_written() >= self.config.max_file_bytes
+            || self
+                .config
+                .rotate_interval
+                .is_some_and(|interval| file.opened_at.elapsed() >= interval)
+    }
+
+    /// Applies the retention policy, never touching the file currently being written
+    fn prune(&self, current: &Path) {
+        let mut files = match archived_files(&self.config.path) {
+            Ok(files) => files,
+            Err(e) => {
+                warn!(%e, "Unable to list archive files for retention");
+                return;
+            }
+        };
+        files.retain(|(path, _)| path != current);
+
+        let now = SystemTime::now();
+        let expired = |modified: &SystemTime| {
+            self.config.retention.is_some_and(|retention| {
+                now.duration_since(*modified)
+                    .is_ok_and(|age| age > retention)
+            })
+        };
+
+        // Oldest first, so anything over `max_files` is dropped from the front
+        let excess = self
+            .config
+            .max_files
+            .map(|max| (files.len() + 1).saturating_sub(max))
+            .unwrap_or(0);
+
+        for (i, (path, modified)) in files.iter().enumerate() {
+            if i < excess || expired(modified) {
+                match fs::remove_file(path) {
+                    Ok(()) => info!(?path, "Removed archive file"),
+                    Err(e) => warn!(%e, ?path, "Failed to remove archive file"),
+                }
+            }
+        }
+    }
+}
+
+impl ArchiveFile {
+    fn create(config: &ArchiveConfig) -> Result<Self> {
+        let stamp = Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+
+        let (path, writer) = match config.format {
+            ArchiveFormat::Jsonl => {
+                let path = config.path.join(format!("{FILE_PREFIX}{stamp}.jsonl"));
+                let writer = BufWriter::new(File::create(&path)?);
+                (path, ArchiveWriter::Jsonl { writer, bytes: 0 })
+            }
+            #[cfg(feature = "parquet")]
+            ArchiveFormat::Parquet => {
+                let path = config.path.join(format!("{FILE_PREFIX}{stamp}.parquet"));
+                let writer = parquet::ParquetFile::create(&path)?;
+                (path, ArchiveWriter::Parquet(Box::new(writer)))
+            }
+            #[cfg(not(feature = "parquet"))]
+            ArchiveFormat::Parquet => unreachable!("rejected in Archive::new"),
+        };
+
+        info!(?path, "Opened archive file");
+
+        Ok(Self {
+            path,
+            opened_at: Instant::now(),
+            writer,
+        })
+    }
+
+    fn close(self) -> Result<()> {
+        match self.writer {
+            ArchiveWriter::Jsonl { mut writer, .. } => writer.flush()?,
+            #[cfg(feature = "parquet")]
+            ArchiveWriter::Parquet(writer) => writer.close()?,
+        }
+
+        info!(path = ?self.path, "Closed archive file");
+        Ok(())
+    }
+}
+
+impl ArchiveWriter {
+    fn write(&mut self, event: &ProbeEvent) -> Result<()> {
+        match self {
+            ArchiveWriter::Jsonl { writer, bytes } => {
+                let mut line = serde_json::to_vec(event)?;
+                line.push(b'\n');
+                writer.write_all(&line)?;
+                *bytes += line.len() as u64;
+            }
+            #[cfg(feature = "parquet")]
+            ArchiveWriter::Parquet(writer) => writer.write(event)?,
+        }
+
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> u64 {
+        match self {
+            ArchiveWriter::Jsonl { bytes, .. } => *bytes,
+            #[cfg(feature = "parquet")]
+            ArchiveWriter::Parquet(writer) => writer.bytes_written(),
+        }
+    }
+}
+
+/// Events already queued on the bus, without waiting for more
+fn drain(events: &mut broadcast::Receiver<Arc<ProbeEvent>>) -> Vec<Arc<ProbeEvent>> {
+    let mut batch = Vec::new();
+
+    loop {
+        match events.try_recv() {
+            Ok(event) => batch.push(event),
+            Err(TryRecvError::Lagged(missed)) => {
+                warn!(missed, "Archive fell behind, probe results were dropped");
+            }
+            Err(_) => return batch,
+        }
+    }
+}
+
+/// Lists finished and in-progress archive files, oldest first
+fn archived_files(dir: &Path) -> Result<Vec<(PathBuf, SystemTime)>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let is_archive = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(FILE_PREFIX));
+
+        if is_archive && entry.file_type()?.is_file() {
+            files.push((entry.path(), entry.metadata()?.modified()?));
+        }
+    }
+
+    // File names embed the creation time, so they sort chronologically
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
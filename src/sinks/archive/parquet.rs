@@ -0,0 +1,123 @@
+use crate::events::ProbeEvent;
+use color_eyre::eyre::Result;
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Rows are buffered and written as a single row group once this many are collected
+const ROW_GROUP_SIZE: usize = 1024;
+
+const SCHEMA: &str = "
+message probe_result {
+    REQUIRED BYTE_ARRAY check_id (UTF8);
+    REQUIRED BYTE_ARRAY protocol (UTF8);
+    REQUIRED BYTE_ARRAY endpoint (UTF8);
+    REQUIRED INT64 timestamp (TIMESTAMP(MILLIS, true));
+    REQUIRED BYTE_ARRAY response (JSON);
+}
+";
+
+#[derive(Default)]
+struct Rows {
+    check_id: Vec<ByteArray>,
+    protocol: Vec<ByteArray>,
+    endpoint: Vec<ByteArray>,
+    timestamp: Vec<i64>,
+    response: Vec<ByteArray>,
+    bytes: u64,
+}
+
+/// A Parquet archive file. It is only readable once closed.
+pub struct ParquetFile {
+    writer: SerializedFileWriter<File>,
+    rows: Rows,
+}
+
+impl ParquetFile {
+    pub fn create(path: &Path) -> Result<Self> {
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let props = Arc::new(
+            WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build(),
+        );
+
+        Ok(Self {
+            writer: SerializedFileWriter::new(File::create(path)?, schema, props)?,
+            rows: Rows::default(),
+        })
+    }
+
+    pub fn write(&mut self, event: &ProbeEvent) -> Result<()> {
+        let response = serde_json::to_vec(&event.response)?;
+        let rows = &mut self.rows;
+
+        rows.bytes += (event.check_id.len() + event.endpoint.len() + response.len()) as u64;
+        rows.check_id.push(event.check_id.as_str().into());
+        rows.protocol.push(event.protocol.into());
+        rows.endpoint.push(event.endpoint.as_str().into());
+        rows.timestamp.push(event.timestamp.timestamp_millis());
+        rows.response.push(response.into());
+
+        if rows.timestamp.len() >= ROW_GROUP_SIZE {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Bytes flushed to disk plus an estimate of the rows still buffered
+    pub fn bytes_written(&self) -> u64 {
+        self.writer.bytes_written() as u64 + self.rows.bytes
+    }
+
+    pub fn close(mut self) -> Result<()> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.rows.timestamp.is_empty() {
+            return Ok(());
+        }
+
+        let rows = std::mem::take(&mut self.rows);
+        let mut row_group = self.writer.next_row_group()?;
+
+        for values in [&rows.check_id, &rows.protocol, &rows.endpoint] {
+            write_byte_array_column(&mut row_group, values)?;
+        }
+
+        if let Some(mut column) = row_group.next_column()? {
+            column
+                .typed::<Int64Type>()
+                .write_batch(&rows.timestamp, None, None)?;
+            column.close()?;
+        }
+
+        write_byte_array_column(&mut row_group, &rows.response)?;
+
+        row_group.close()?;
+        Ok(())
+    }
+}
+
+fn write_byte_array_column(
+    row_group: &mut parquet::file::writer::SerializedRowGroupWriter<'_, File>,
+    values: &[ByteArray],
+) -> Result<()> {
+    if let Some(mut column) = row_group.next_column()? {
+        column
+            .typed::<ByteArrayType>()
+            .write_batch(values, None, None)?;
+        column.close()?;
+    }
+
+    Ok(())
+}
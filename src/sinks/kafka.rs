@@ -0,0 +1,225 @@
+use super::record::ProbeRecord;
+use crate::config::{KafkaConfig, KafkaKey, RecordFormat};
+use crate::events::ProbeEvent;
+use apache_avro::{GenericSingleObjectWriter, Schema};
+use color_eyre::eyre::{eyre, Result};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::DeliveryResult;
+use rdkafka::producer::{BaseRecord, Producer, ProducerContext, ThreadedProducer};
+use rdkafka::{ClientConfig, ClientContext};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+use tracing::{error, info, warn};
+
+/// How long to wait for buffered records to be delivered on shutdown
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to back off when the local buffer is full because the broker is unavailable
+const QUEUE_FULL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// How long a record waits for room in a full buffer before it is dropped
+const QUEUE_FULL_MAX_WAIT: Duration = Duration::from_secs(30);
+
+const AVRO_SCHEMA: &str = r#"
+{
+    "type": "record",
+    "name": "ProbeRecord",
+    "namespace": "com.bitping.distributed_metrics",
+    "fields": [
+        { "name": "version", "type": "int" },
+        { "name": "check_id", "type": "string" },
+        { "name": "endpoint", "type": "string" },
+        { "name": "protocol", "type": "string" },
+        { "name": "timestamp", "type": { "type": "long", "logicalType": "timestamp-millis" } },
+        { "name": "node_info", "type": ["null", "string"], "doc": "JSON encoded node info" },
+        { "name": "result", "type": ["null", "string"], "doc": "JSON encoded probe result" },
+        { "name": "error", "type": ["null", "string"] },
+        { "name": "duration_ms", "type": ["null", "double"] }
+    ]
+}
+"#;
+
+/// Avro has no free-form map type, so nested JSON is carried as strings
+#[derive(Serialize)]
+struct AvroProbeRecord<'a> {
+    version: i32,
+    check_id: &'a str,
+    endpoint: &'a str,
+    protocol: &'a str,
+    timestamp: i64,
+    node_info: Option<String>,
+    result: Option<String>,
+    error: Option<&'a str>,
+    duration_ms: Option<f64>,
+}
+
+struct DeliveryLogger;
+
+impl ClientContext for DeliveryLogger {}
+
+impl ProducerContext for DeliveryLogger {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        if let Err((e, _)) = result {
+            error!(%e, "Failed to deliver probe record to Kafka");
+        }
+    }
+}
+
+/// Publishes every probe outcome to a Kafka topic.
+///
+/// Records are buffered by librdkafka while the broker is unavailable and
+/// delivered once it comes back, up to `buffer_messages`.
+pub struct KafkaSink {
+    config: &'static KafkaConfig,
+    producer: ThreadedProducer<DeliveryLogger>,
+    avro: Option<(Schema, GenericSingleObjectWriter)>,
+}
+
+impl KafkaSink {
+    pub fn new(config: &'static KafkaConfig) -> Result<Self> {
+        let mut client_config = ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", &config.brokers)
+            .set("enable.idempotence", config.idempotent.to_string())
+            .set(
+                "queue.buffering.max.messages",
+                config.buffer_messages.to_string(),
+            )
+            .set(
+                "message.timeout.ms",
+                config
+                    .delivery_timeout
+                    .map_or(0, |t| t.as_millis())
+                    .to_string(),
+            );
+
+        if config.idempotent {
+            client_config.set("acks", "all");
+        }
+
+        for (key, value) in &config.properties {
+            client_config.set(key, value);
+        }
+
+        let producer = client_config.create_with_context(DeliveryLogger)?;
+
+        let avro = match config.format {
+            RecordFormat::Json => None,
+            RecordFormat::Avro => {
+                let schema = Schema::parse_str(AVRO_SCHEMA)?;
+                let writer = GenericSingleObjectWriter::new_with_capacity(&schema, 1024)?;
+                Some((schema, writer))
+            }
+        };
+
+        Ok(Self {
+            config,
+            producer,
+            avro,
+        })
+    }
+
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<Arc<ProbeEvent>>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        info!(brokers = %self.config.brokers, topic = %self.config.topic, "Publishing probe records to Kafka");
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        if let Err(e) = self.publish(&event, &mut shutdown).await {
+                            error!(%e, "Failed to publish probe record to Kafka");
+                        }
+                        // Shutdown may have been seen while waiting for the buffer
+                        if *shutdown.borrow() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(missed, "Kafka sink fell behind, probe records were dropped");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = shutdown.changed() => break,
+            }
+        }
+
+        if let Err(e) = self.producer.flush(FLUSH_TIMEOUT) {
+            error!(%e, "Failed to flush buffered probe records to Kafka");
+        }
+    }
+
+    /// Waits for room while the buffer is full, giving up on the record after
+    /// `QUEUE_FULL_MAX_WAIT` or as soon as shutdown begins
+    async fn publish(
+        &mut self,
+        event: &ProbeEvent,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> Result<()> {
+        let record = ProbeRecord::from(event);
+        let key = match self.config.key {
+            KafkaKey::Endpoint => record.endpoint,
+            KafkaKey::Check => record.check_id,
+        };
+        let payload = self.encode(&record)?;
+        let started = Instant::now();
+
+        loop {
+            match self.producer.send(
+                BaseRecord::to(&self.config.topic)
+                    .key(key)
+                    .payload(&payload),
+            ) {
+                Ok(()) => return Ok(()),
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {
+                    if started.elapsed() >= QUEUE_FULL_MAX_WAIT {
+                        return Err(eyre!("Kafka buffer stayed full, probe record dropped"));
+                    }
+
+                    warn!("Kafka buffer is full, waiting for the broker to catch up");
+                    tokio::select! {
+                        _ = tokio::time::sleep(QUEUE_FULL_BACKOFF) => {}
+                        _ = shutdown.changed() => {
+                            return Err(eyre!("Shutting down with a full Kafka buffer, probe record dropped"));
+                        }
+                    }
+                }
+                Err((e, _)) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn encode(&mut self, record: &ProbeRecord) -> Result<Vec<u8>> {
+        let Some((schema, writer)) = &mut self.avro else {
+            return Ok(serde_json::to_vec(record)?);
+        };
+
+        let avro_record = AvroProbeRecord {
+            version: record.version as i32,
+            check_id: record.check_id,
+            endpoint: record.endpoint,
+            protocol: record.protocol,
+            timestamp: record.timestamp.timestamp_millis(),
+            node_info: record.node_info.map(|v| v.to_string()),
+            result: record.result.map(|v| v.to_string()),
+            error: record.error,
+            duration_ms: record.duration_ms,
+        };
+
+        let value = apache_avro::to_value(avro_record)?
+            .resolve(schema)
+            .map_err(|e| eyre!("Probe record does not match Avro schema: {e}"))?;
+
+        let mut payload = Vec::new();
+        writer.write_value(value, &mut payload)?;
+        Ok(payload)
+    }
+}
@@ -0,0 +1,214 @@
+use super::record::ProbeRecord;
+use crate::config::LokiConfig;
+use crate::events::ProbeEvent;
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+use tracing::{error, info, warn};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Batches are retried until this many times `batch_size` lines are waiting, then dropped
+const MAX_PENDING_BATCHES: usize = 10;
+
+type StreamLabels = BTreeMap<&'static str, String>;
+
+#[derive(Serialize)]
+struct PushRequest<'a> {
+    streams: Vec<Stream<'a>>,
+}
+
+#[derive(Serialize)]
+struct Stream<'a> {
+    stream: &'a StreamLabels,
+    values: &'a [[String; 2]],
+}
+
+/// Pushes one structured log line per probe to Loki, so failures keep their
+/// original error message and can be searched next to the metrics.
+pub struct LokiSink {
+    config: &'static LokiConfig,
+    client: reqwest::Client,
+    streams: HashMap<StreamLabels, Vec<[String; 2]>>,
+    pending: usize,
+}
+
+impl LokiSink {
+    pub fn new(config: &'static LokiConfig) -> Self {
+        Self {
+            config,
+            // A hung Loki would otherwise hold up every batch behind it
+            client: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("failed to build Loki client"),
+            streams: HashMap::new(),
+            pending: 0,
+        }
+    }
+
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<Arc<ProbeEvent>>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        info!(url = %self.config.url, "Pushing probe events to Loki");
+
+        let mut flush_interval = tokio::time::interval(self.config.batch_interval);
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        self.push_line(&event);
+                        if self.pending >= self.config.batch_size {
+                            self.flush().await;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(missed, "Loki sink fell behind, probe events were dropped");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = flush_interval.tick() => self.flush().await,
+                _ = shutdown.changed() => break,
+            }
+        }
+
+        self.flush().await;
+    }
+
+    fn push_line(&mut self, event: &ProbeEvent) {
+        let record = ProbeRecord::from(event);
+
+        let mut labels: StreamLabels = self
+            .config
+            .labels
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.clone()))
+            .collect();
+        labels.insert("protocol", record.protocol.to_string());
+        labels.insert("check", record.check_id.to_string());
+        if let Some(country) = record
+            .node_info
+            .and_then(|n| n.get("countryCode")?.as_str())
+        {
+            labels.insert("country", country.to_string());
+        }
+
+        let line = json!({
+            "level": if record.error.is_some() { "error" } else { "info" },
+            "endpoint": record.endpoint,
+            "error": record.error,
+            "duration_ms": record.duration_ms,
+            "node": record.node_info.map(summarize_node),
+            "result": record.result.map(|r| summarize_result(record.protocol, r)),
+        });
+
+        let timestamp = event
+            .timestamp
+            .timestamp_nanos_opt()
+            .unwrap_or_default()
+            .to_string();
+
+        self.streams
+            .entry(labels)
+            .or_default()
+            .push([timestamp, line.to_string()]);
+        self.pending += 1;
+    }
+
+    async fn flush(&mut self) {
+        if self.pending == 0 {
+            return;
+        }
+
+        match self.push().await {
+            Ok(()) => {
+                self.streams.clear();
+                self.pending = 0;
+            }
+            Err(e) => {
+                error!(%e, pending = self.pending, "Failed to push probe events to Loki");
+
+                if self.pending >= self.config.batch_size * MAX_PENDING_BATCHES {
+                    warn!(
+                        dropped = self.pending,
+                        "Loki is unavailable, dropping buffered probe events"
+                    );
+                    self.streams.clear();
+                    self.pending = 0;
+                }
+            }
+        }
+    }
+
+    async fn push(&self) -> Result<()> {
+        let body = PushRequest {
+            streams: self
+                .streams
+                .iter()
+                .map(|(stream, values)| Stream { stream, values })
+                .collect(),
+        };
+
+        let mut request = self
+            .client
+            .post(format!(
+                "{}/loki/api/v1/push",
+                self.config.url.trim_end_matches('/')
+            ))
+            .json(&body);
+
+        if let Some(tenant_id) = &self.config.tenant_id {
+            request = request.header("X-Scope-OrgID", tenant_id);
+        }
+
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Keeps only the fields that identify where the probe ran from
+fn summarize_node(node_info: &Value) -> Value {
+    json!({
+        "country_code": node_info.get("countryCode"),
+        "continent": node_info.get("continentCode"),
+        "region": node_info.get("regionName"),
+        "city": node_info.get("city"),
+        "isp": node_info.get("isp"),
+        "mobile": node_info.get("mobile"),
+        "residential": node_info.get("residential"),
+        "proxy": node_info.get("proxy"),
+    })
+}
+
+/// HTTP bodies and HLS fragment lists are too large for a log line,
+/// the archive sink keeps those in full.
+fn summarize_result(protocol: &str, result: &Value) -> Value {
+    match protocol {
+        "http" => json!({
+            "status_code": result.get("statusCode"),
+            "body_hash": result.get("bodyHash"),
+            "matches": result.get("matches"),
+        }),
+        "hls" => {
+            let master = result.get("master");
+            json!({
+                "master": master.and_then(|m| m.get("file")),
+                "renditions_count": master
+                    .and_then(|m| m.get("renditions")?.as_array())
+                    .map(Vec::len),
+                "rendition": result.get("rendition").and_then(|r| r.get("file")),
+            })
+        }
+        _ => result.clone(),
+    }
+}
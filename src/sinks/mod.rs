@@ -0,0 +1,53 @@
+use crate::config::Conf;
+use crate::events;
+use color_eyre::eyre::Result;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+pub mod archive;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod loki;
+mod record;
+pub mod webhook;
+
+/// Spawns every configured sink. Sinks subscribe to probe events immediately,
+/// so this must be called before the collectors start.
+pub fn spawn_sinks(
+    config: &'static Conf,
+    join_set: &mut JoinSet<()>,
+    shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    if let Some(archive_config) = &config.global_config.archive {
+        let archive = archive::Archive::new(archive_config)?;
+        join_set.spawn(archive.run(events::subscribe(), shutdown.clone()));
+    }
+
+    if let Some(loki_config) = &config.global_config.loki {
+        let loki = loki::LokiSink::new(loki_config);
+        join_set.spawn(loki.run(events::subscribe(), shutdown.clone()));
+    }
+
+    for webhook_config in &config.global_config.webhooks {
+        let webhook = webhook::WebhookSink::new(webhook_config);
+        join_set.spawn(webhook.run(events::subscribe(), shutdown.clone()));
+    }
+
+    if let Some(kafka_config) = &config.global_config.kafka {
+        #[cfg(feature = "kafka")]
+        {
+            let kafka = kafka::KafkaSink::new(kafka_config)?;
+            join_set.spawn(kafka.run(events::subscribe(), shutdown.clone()));
+        }
+
+        #[cfg(not(feature = "kafka"))]
+        {
+            let _ = kafka_config;
+            color_eyre::eyre::bail!(
+                "Kafka sink is configured but this binary was built without the `kafka` feature"
+            );
+        }
+    }
+
+    Ok(())
+}
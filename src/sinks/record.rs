@@ -0,0 +1,43 @@
+use crate::events::ProbeEvent;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Bumped whenever a field of [`ProbeRecord`] is renamed, removed or changes meaning
+pub const RECORD_VERSION: u32 = 1;
+
+/// A flattened, versioned view of a [`ProbeEvent`] for sinks that publish
+/// one record per probe outcome.
+#[derive(Serialize, Debug)]
+pub struct ProbeRecord<'a> {
+    pub version: u32,
+    pub check_id: &'a str,
+    pub endpoint: &'a str,
+    pub protocol: &'a str,
+    pub timestamp: DateTime<Utc>,
+    pub node_info: Option<&'a Value>,
+    pub result: Option<&'a Value>,
+    pub error: Option<&'a str>,
+    pub duration_ms: Option<f64>,
+}
+
+impl<'a> From<&'a ProbeEvent> for ProbeRecord<'a> {
+    fn from(event: &'a ProbeEvent) -> Self {
+        let first_result = event.first_result();
+
+        Self {
+            version: RECORD_VERSION,
+            check_id: &event.check_id,
+            endpoint: &event.endpoint,
+            protocol: event.protocol,
+            timestamp: event.timestamp,
+            node_info: event.node_info(),
+            result: first_result.and_then(|r| r.get("result")),
+            error: event
+                .error
+                .as_deref()
+                .or_else(|| first_result.and_then(|r| r.get("error")?.as_str())),
+            duration_ms: event.duration_ms(),
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use super::record::ProbeRecord;
+use crate::collectors::ProbeOutcome;
+use crate::config::{WebhookConfig, WebhookTemplate};
+use crate::events::ProbeEvent;
+use crate::template;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+use tracing::{error, info, warn};
+
+const GENERIC_TEMPLATE: &str = r#"{
+  "status": "{{status}}",
+  "check": "{{check}}",
+  "endpoint": "{{endpoint}}",
+  "protocol": "{{protocol}}",
+  "error": "{{error}}",
+  "consecutive_failures": {{consecutive_failures}},
+  "since": "{{since}}",
+  "timestamp": "{{timestamp}}",
+  "country": "{{country}}",
+  "isp": "{{isp}}",
+  "summary": "{{summary}}"
+}"#;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+const SLACK_TEMPLATE: &str = r#"{"text": "{{summary}}"}"#;
+
+const DISCORD_TEMPLATE: &str = r#"{"content": "{{summary}}"}"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Notification {
+    Firing,
+    Resolved,
+}
+
+impl Notification {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Notification::Firing => "firing",
+            Notification::Resolved => "resolved",
+        }
+    }
+}
+
+#[derive(Default)]
+struct CheckState {
+    firing: bool,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    firing_since: Option<DateTime<Utc>>,
+    last_sent: Option<Instant>,
+}
+
+impl CheckState {
+    /// Applies a probe outcome and returns the notification it calls for, if any
+    fn update(
+        &mut self,
+        config: &WebhookConfig,
+        outcome: ProbeOutcome,
+        timestamp: DateTime<Utc>,
+    ) -> Option<Notification> {
+        match outcome {
+            ProbeOutcome::Failure => {
+                self.consecutive_failures += 1;
+                self.consecutive_successes = 0;
+
+                let repeat_due = config
+                    .repeat_interval
+                    .zip(self.last_sent)
+                    .is_some_and(|(interval, sent)| sent.elapsed() >= interval);
+
+                if !self.firing && self.consecutive_failures >= config.failure_threshold {
+                    self.firing = true;
+                    self.firing_since = Some(timestamp);
+                    Some(Notification::Firing)
+                } else if self.firing && repeat_due {
+                    Some(Notification::Firing)
+                } else {
+                    None
+                }
+            }
+            ProbeOutcome::Success => {
+                self.consecutive_successes += 1;
+
+                if self.firing && self.consecutive_successes >= config.recovery_threshold {
+                    self.firing = false;
+                    if config.send_resolved {
+                        return Some(Notification::Resolved);
+                    }
+                    // Nothing is sent, but the next outage must start counting from zero
+                    self.resolve();
+                } else if !self.firing {
+                    self.consecutive_failures = 0;
+                }
+                None
+            }
+        }
+    }
+
+    fn resolve(&mut self) {
+        self.consecutive_failures = 0;
+        self.firing_since = None;
+    }
+}
+
+/// Posts a templated payload to a webhook when a check starts failing or recovers.
+///
+/// State is tracked per check from the outcome each collector reports, so a single
+/// failing probe only alerts once `failure_threshold` is reached.
+pub struct WebhookSink {
+    config: &'static WebhookConfig,
+    client: reqwest::Client,
+    /// Keyed by check id, which the config guarantees to be unique
+    checks: HashMap<String, CheckState>,
+}
+
+impl WebhookSink {
+    pub fn new(config: &'static WebhookConfig) -> Self {
+        Self {
+            config,
+            // Notifications are sent inline, a hung webhook would stall state tracking
+            client: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("failed to build webhook client"),
+            checks: HashMap::new(),
+        }
+    }
+
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<Arc<ProbeEvent>>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        info!(url = %self.config.url, "Sending check state changes to webhook");
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => self.observe(&event).await,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(missed, "Webhook sink fell behind, probe events were dropped");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = shutdown.changed() => break,
+            }
+        }
+    }
+
+    async fn observe(&mut self, event: &ProbeEvent) {
+        // Failed requests to the Bitping API say nothing about the endpoint itself
+        let Some(outcome) = event.outcome else {
+            return;
+        };
+
+        if !self.config.checks.is_empty() && !self.config.checks.contains(&event.check_id) {
+            return;
+        }
+
+        let config = self.config;
+        let state = self.checks.entry(event.check_id.clone()).or_default();
+
+        let notification = state.update(config, outcome, event.timestamp);
+        let Some(notification) = notification else {
+            return;
+        };
+
+        let body = render_payload(config, notification, state, event);
+
+        // The resolved payload still reports how long the check was down
+        if notification == Notification::Resolved {
+            state.resolve();
+        }
+        state.last_sent = Some(Instant::now());
+
+        if let Err(e) = self.send(body).await {
+            error!(%e, check = %event.check_id, "Failed to send webhook notification");
+        } else {
+            info!(check = %event.check_id, status = notification.as_str(), "Sent webhook notification");
+        }
+    }
+
+    async fn send(&self, body: String) -> Result<()> {
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header("content-type", "application/json")
+            .body(body);
+
+        for (key, value) in &self.config.headers {
+            request = request.header(key, value);
+        }
+
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+fn render_payload(
+    config: &WebhookConfig,
+    notification: Notification,
+    state: &CheckState,
+    event: &ProbeEvent,
+) -> String {
+    let record = ProbeRecord::from(event);
+    let node = |field: &str| {
+        record
+            .node_info
+            .and_then(|n| n.get(field)?.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let summary = match notification {
+        Notification::Firing => {
+            let mut summary = format!(
+                "[FIRING] {} ({}) is failing after {} consecutive failures",
+                record.check_id, record.protocol, state.consecutive_failures,
+            );
+            // Failed assertions and unexpected answers come without an error
+            if let Some(error) = record.error {
+                summary.push_str(": ");
+                summary.push_str(error);
+            }
+            summary
+        }
+        Notification::Resolved => format!(
+            "[RESOLVED] {} ({}) has recovered",
+            record.check_id, record.protocol
+        ),
+    };
+
+    let body = match (&config.body, config.template) {
+        (Some(body), _) => body.as_str(),
+        (None, WebhookTemplate::Generic) => GENERIC_TEMPLATE,
+        (None, WebhookTemplate::Slack) => SLACK_TEMPLATE,
+        (None, WebhookTemplate::Discord) => DISCORD_TEMPLATE,
+    };
+
+    template::render(
+        body,
+        |name| match name {
+            "status" => Some(notification.as_str().to_string()),
+            "check" => Some(record.check_id.to_string()),
+            "endpoint" => Some(record.endpoint.to_string()),
+            "protocol" => Some(record.protocol.to_string()),
+            "error" => record.error.map(str::to_string),
+            "consecutive_failures" => Some(state.consecutive_failures.to_string()),
+            "since" => state.firing_since.map(|s| s.to_rfc3339()),
+            "timestamp" => Some(record.timestamp.to_rfc3339()),
+            "country" => Some(node("countryCode")),
+            "city" => Some(node("city")),
+            "isp" => Some(node("isp")),
+            "summary" => Some(summary.clone()),
+            _ => None,
+        },
+        template::escape_json,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use figment::providers::{Format, Yaml};
+    use figment::Figment;
+
+    fn config(yaml: &str) -> WebhookConfig {
+        Figment::from(Yaml::string(&format!("url: http://localhost\n{yaml}")))
+            .extract()
+            .unwrap()
+    }
+
+    fn run(state: &mut CheckState, config: &WebhookConfig, outcomes: &str) -> Vec<Notification> {
+        outcomes
+            .chars()
+            .filter_map(|outcome| {
+                let outcome = match outcome {
+                    'F' => ProbeOutcome::Failure,
+                    _ => ProbeOutcome::Success,
+                };
+                state.update(config, outcome, Utc::now())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_and_resolves_once() {
+        let config = config("failure_threshold: 3\nrecovery_threshold: 2");
+        let mut state = CheckState::default();
+
+        assert_eq!(run(&mut state, &config, "FFSFF"), []);
+        assert_eq!(run(&mut state, &config, "F"), [Notification::Firing]);
+        assert!(state.firing_since.is_some());
+        assert_eq!(run(&mut state, &config, "FFSFS"), []);
+        assert_eq!(run(&mut state, &config, "S"), [Notification::Resolved]);
+
+        // The payload is rendered from the state before it is reset
+        assert_eq!(state.consecutive_failures, 6);
+        state.resolve();
+        assert_eq!(run(&mut state, &config, "FF"), []);
+        assert_eq!(run(&mut state, &config, "F"), [Notification::Firing]);
+    }
+
+    #[test]
+    fn resets_on_recovery_without_resolved_notifications() {
+        let config = config("failure_threshold: 2\nsend_resolved: false");
+        let mut state = CheckState::default();
+
+        assert_eq!(run(&mut state, &config, "FF"), [Notification::Firing]);
+        assert_eq!(run(&mut state, &config, "S"), []);
+        assert!(!state.firing);
+        assert_eq!(state.consecutive_failures, 0);
+        assert_eq!(state.firing_since, None);
+
+        // A single failure after the recovery is not an outage yet
+        assert_eq!(run(&mut state, &config, "F"), []);
+        assert_eq!(run(&mut state, &config, "F"), [Notification::Firing]);
+    }
+
+    #[test]
+    fn repeats_while_firing() {
+        let config = config("failure_threshold: 1\nrepeat_interval: 0s");
+        let mut state = CheckState::default();
+
+        assert_eq!(run(&mut state, &config, "F"), [Notification::Firing]);
+        // Nothing was sent yet, so there is nothing to repeat
+        assert_eq!(run(&mut state, &config, "F"), []);
+        state.last_sent = Some(Instant::now());
+        assert_eq!(run(&mut state, &config, "F"), [Notification::Firing]);
+    }
+}
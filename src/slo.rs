@@ -0,0 +1,389 @@
+use crate::collectors::ProbeOutcome;
+use crate::config::{Conf, MetricConfig, SloConfig};
+use crate::events::{self, ProbeEvent};
+use crate::state;
+use metrics::gauge;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+const STATE_KEY: &str = "slo";
+
+/// Recent probe outcomes are counted in buckets of this many seconds
+const BUCKET_SECS: i64 = 60;
+
+/// Outcomes older than this are merged into hourly buckets, so a 30 day window holds
+/// about a thousand buckets instead of tens of thousands. Burn rates over windows up to
+/// this long stay exact to the minute.
+const FINE_SECS: i64 = 6 * 60 * 60;
+
+const COARSE_BUCKET_SECS: i64 = 60 * 60;
+
+/// Burn rate windows recommended for multi-window, multi-burn-rate alerting
+const BURN_RATE_WINDOWS: [(&str, i64); 7] = [
+    ("5m", 5 * 60),
+    ("30m", 30 * 60),
+    ("1h", 60 * 60),
+    ("2h", 2 * 60 * 60),
+    ("6h", 6 * 60 * 60),
+    ("1d", 24 * 60 * 60),
+    ("3d", 3 * 24 * 60 * 60),
+];
+
+/// Label value of the series aggregated across every continent
+const ALL_CONTINENTS: &str = "all";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct Bucket {
+    start: i64,
+    good: u32,
+    total: u32,
+}
+
+/// Probe outcomes over a rolling window, oldest bucket first
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct Window {
+    /// The last [`FINE_SECS`], per minute
+    buckets: VecDeque<Bucket>,
+    /// Anything older, per hour
+    #[serde(default)]
+    hours: VecDeque<Bucket>,
+}
+
+impl Window {
+    fn record(&mut self, now: i64, good: bool) {
+        add(&mut self.buckets, now, BUCKET_SECS, good as u32, 1);
+    }
+
+    fn prune(&mut self, now: i64, keep_secs: i64) {
+        while let Some(bucket) = self
+            .buckets
+            .front()
+            .filter(|b| b.start + BUCKET_SECS <= now - FINE_SECS)
+            .copied()
+        {
+            self.buckets.pop_front();
+            add(
+                &mut self.hours,
+                bucket.start,
+                COARSE_BUCKET_SECS,
+                bucket.good,
+                bucket.total,
+            );
+        }
+
+        while self
+            .hours
+            .front()
+            .is_some_and(|b| b.start + COARSE_BUCKET_SECS <= now - keep_secs)
+        {
+            self.hours.pop_front();
+        }
+    }
+
+    /// Good and total probes over the last `secs`, to the hour beyond [`FINE_SECS`]
+    fn totals(&self, now: i64, secs: i64) -> (u64, u64) {
+        let recent = self
+            .buckets
+            .iter()
+            .rev()
+            .take_while(|b| b.start + BUCKET_SECS > now - secs);
+
+        let older = self
+            .hours
+            .iter()
+            .rev()
+            .take_while(|b| secs > FINE_SECS && b.start + COARSE_BUCKET_SECS > now - secs);
+
+        recent.chain(older).fold((0, 0), |(good, total), b| {
+            (good + b.good as u64, total + b.total as u64)
+        })
+    }
+}
+
+/// Adds outcomes to the bucket of the given size `timestamp` falls in, which is the last
+/// one or a new one as outcomes arrive in order
+fn add(buckets: &mut VecDeque<Bucket>, timestamp: i64, size: i64, good: u32, total: u32) {
+    let start = timestamp - timestamp.rem_euclid(size);
+
+    match buckets.back_mut() {
+        Some(bucket) if bucket.start == start => {
+            bucket.total += total;
+            bucket.good += good;
+        }
+        _ => buckets.push_back(Bucket { start, good, total }),
+    }
+}
+
+struct TrackedCheck {
+    check: &'static MetricConfig,
+    slo: &'static SloConfig,
+    /// Keyed by continent code, or [`ALL_CONTINENTS`]
+    windows: HashMap<String, Window>,
+}
+
+/// Evaluates each check's SLO in-process from the outcomes its collector reports.
+///
+/// Exposes availability, remaining error budget and burn rates per check, and
+/// optionally per continent. Windows survive restarts when state is persisted.
+pub struct SloTracker {
+    checks: HashMap<String, TrackedCheck>,
+    refresh_interval: Duration,
+}
+
+pub fn spawn_trackers(
+    config: &'static Conf,
+    join_set: &mut JoinSet<()>,
+    shutdown: watch::Receiver<bool>,
+) {
+    let mut restored: HashMap<String, HashMap<String, Window>> =
+        state::get(STATE_KEY).unwrap_or_default();
+
+    let checks: HashMap<String, TrackedCheck> = config
+        .metrics
+        .iter()
+        .map(|metric| metric.common_config())
+        .filter_map(|check| {
+            let slo = check.slo.as_ref()?;
+            let windows = restored.remove(check.check_id()).unwrap_or_default();
+
+            Some((
+                check.check_id().to_string(),
+                TrackedCheck {
+                    check,
+                    slo,
+                    windows,
+                },
+            ))
+        })
+        .collect();
+
+    if checks.is_empty() {
+        return;
+    }
+
+    // Gauges not updated within the clear timeout are dropped from the exporter
+    let refresh_interval =
+        (config.global_config.metric_clear_timeout / 2).max(Duration::from_secs(1));
+
+    let tracker = SloTracker {
+        checks,
+        refresh_interval,
+    };
+    join_set.spawn(tracker.run(events::subscribe(), shutdown));
+}
+
+impl SloTracker {
+    async fn run(
+        mut self,
+        mut events: broadcast::Receiver<Arc<ProbeEvent>>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        info!(checks = self.checks.len(), "Tracking SLOs");
+        self.describe();
+
+        let mut refresh = tokio::time::interval(self.refresh_interval);
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => self.observe(&event),
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(missed, "SLO tracker fell behind, probe outcomes were dropped");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = refresh.tick() => self.refresh(),
+                _ = shutdown.changed() => break,
+            }
+        }
+
+        self.persist();
+    }
+
+    fn describe(&self) {
+        for tracked in self.checks.values() {
+            let prefix = &tracked.check.prefix;
+
+            metrics::describe_gauge!(
+                format!("{}slo_target", prefix),
+                "Ratio of probes that must succeed over the SLO window"
+            );
+            metrics::describe_gauge!(
+                format!("{}slo_availability", prefix),
+                "Ratio of successful probes over the SLO window"
+            );
+            metrics::describe_gauge!(
+                format!("{}slo_error_budget_remaining", prefix),
+                "Ratio of the error budget left over the SLO window, negative once exhausted"
+            );
+            metrics::describe_gauge!(
+                format!("{}slo_burn_rate", prefix),
+                "Rate the error budget is being spent at over a window, 1 spends it exactly over the SLO window"
+            );
+        }
+    }
+
+    fn observe(&mut self, event: &ProbeEvent) {
+        let Some(tracked) = self.checks.get_mut(&event.check_id) else {
+            return;
+        };
+
+        // Failed requests to the Bitping API say nothing about the endpoint itself
+        let Some(outcome) = event.outcome else {
+            return;
+        };
+
+        let within_latency = match (tracked.slo.success.max_duration_ms, event.duration_ms()) {
+            (Some(max), Some(duration)) => duration <= max,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let good = outcome == ProbeOutcome::Success && within_latency;
+
+        let now = event.timestamp.timestamp();
+        tracked
+            .windows
+            .entry(ALL_CONTINENTS.to_string())
+            .or_default()
+            .record(now, good);
+
+        if tracked.slo.per_continent {
+            let continent = event
+                .node_info()
+                .and_then(|n| n.get("continentCode")?.as_str());
+
+            if let Some(continent) = continent {
+                tracked
+                    .windows
+                    .entry(continent.to_string())
+                    .or_default()
+                    .record(now, good);
+            }
+        }
+
+        tracked.refresh(now);
+    }
+
+    fn refresh(&mut self) {
+        let now = chrono::Utc::now().timestamp();
+
+        for tracked in self.checks.values_mut() {
+            tracked.refresh(now);
+        }
+
+        self.persist();
+    }
+
+    fn persist(&self) {
+        if !state::enabled() {
+            return;
+        }
+
+        let snapshot: HashMap<&str, &HashMap<String, Window>> = self
+            .checks
+            .iter()
+            .map(|(id, tracked)| (id.as_str(), &tracked.windows))
+            .collect();
+
+        state::put(STATE_KEY, &snapshot);
+    }
+}
+
+impl TrackedCheck {
+    fn refresh(&mut self, now: i64) {
+        let prefix = &self.check.prefix;
+        let check_id = self.check.check_id();
+        let window_secs = self.slo.window.as_secs() as i64;
+        let target = self.slo.target / 100.0;
+        let budget = 1.0 - target;
+
+        gauge!(format!("{}slo_target", prefix), "check" => check_id.to_string()).set(target);
+
+        for (continent, window) in &mut self.windows {
+            window.prune(now, window_secs);
+
+            let labels = [
+                ("check", check_id.to_string()),
+                ("continent", continent.clone()),
+            ];
+
+            let (good, total) = window.totals(now, window_secs);
+            if total > 0 {
+                let availability = good as f64 / total as f64;
+                gauge!(format!("{}slo_availability", prefix), &labels).set(availability);
+
+                if budget > 0.0 {
+                    gauge!(format!("{}slo_error_budget_remaining", prefix), &labels)
+                        .set(1.0 - (1.0 - availability) / budget);
+                }
+            }
+
+            if budget <= 0.0 {
+                continue;
+            }
+
+            for (name, secs) in BURN_RATE_WINDOWS {
+                if secs > window_secs {
+                    break;
+                }
+
+                let (good, total) = window.totals(now, secs);
+                if total == 0 {
+                    continue;
+                }
+
+                let error_ratio = (total - good) as f64 / total as f64;
+                let mut labels = labels.to_vec();
+                labels.push(("window", name.to_string()));
+
+                gauge!(format!("{}slo_burn_rate", prefix), &labels).set(error_ratio / budget);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_old_buckets_into_hours() {
+        let mut window = Window::default();
+        let day = 24 * 60 * 60;
+
+        // One probe a minute for two days, every tenth one failing
+        for minute in 0..2 * 24 * 60 {
+            window.record(minute * 60, minute % 10 != 0);
+        }
+
+        let now = 2 * day;
+        window.prune(now, 30 * day);
+
+        assert_eq!(window.buckets.len() as i64, FINE_SECS / BUCKET_SECS);
+        assert_eq!(
+            window.hours.len() as i64,
+            (2 * day - FINE_SECS) / COARSE_BUCKET_SECS
+        );
+        assert_eq!(window.totals(now, 2 * day), (2592, 2880));
+        assert_eq!(window.totals(now, 60 * 60), (54, 60));
+        assert_eq!(window.totals(now, day), (1296, 1440));
+    }
+
+    #[test]
+    fn drops_hours_outside_the_window() {
+        let mut window = Window::default();
+        window.record(0, true);
+        window.record(10 * 60 * 60, false);
+
+        window.prune(12 * 60 * 60, 4 * 60 * 60);
+
+        assert_eq!(window.totals(12 * 60 * 60, 4 * 60 * 60), (0, 1));
+        assert!(window.hours.is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::config::StateConfig;
+use color_eyre::eyre::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{LazyLock, Mutex, OnceLock};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+static STATE_CONFIG: OnceLock<&'static StateConfig> = OnceLock::new();
+
+/// Sections of derived state, keyed by their owner, persisted as a single JSON snapshot
+static STATE: LazyLock<Mutex<HashMap<String, Value>>> = LazyLock::new(Default::default);
+
+/// Called before every save, for owners that only copy their state into the snapshot then
+static SAVE_HOOKS: Mutex<Vec<fn()>> = Mutex::new(Vec::new());
+
+/// Restores the snapshot written by a previous run, if any.
+///
+/// Must be called before anything reads state with [`get`].
+pub fn restore(config: &'static StateConfig) -> Result<()> {
+    let _ = STATE_CONFIG.set(config);
+
+    let contents = match fs::read_to_string(&config.path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!(path = ?config.path, "No state file found, starting fresh");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let sections: HashMap<String, Value> = serde_json::from_str(&contents)?;
+    info!(path = ?config.path, sections = sections.len(), "Restored state");
+    *STATE.lock().unwrap() = sections;
+
+    Ok(())
+}
+
+/// Whether state is being persisted at all
+pub fn enabled() -> bool {
+    STATE_CONFIG.get().is_some()
+}
+
+pub fn get<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let value = STATE.lock().unwrap().get(key)?.clone();
+
+    serde_json::from_value(value)
+        .inspect_err(|e| warn!(%e, key, "Discarding unreadable state section"))
+        .ok()
+}
+
+pub fn put<T: Serialize>(key: &str, value: &T) {
+    if !enabled() {
+        return;
+    }
+
+    match serde_json::to_value(value) {
+        Ok(value) => {
+            STATE.lock().unwrap().insert(key.to_string(), value);
+        }
+        Err(e) => error!(%e, key, "Failed to serialize state section"),
+    }
+}
+
+pub fn before_save(hook: fn()) {
+    SAVE_HOOKS.lock().unwrap().push(hook);
+}
+
+/// Writes the snapshot to disk, replacing the previous one atomically
+pub fn save() -> Result<()> {
+    let Some(config) = STATE_CONFIG.get() else {
+        return Ok(());
+    };
+
+    let hooks = SAVE_HOOKS.lock().unwrap().clone();
+    for hook in hooks {
+        hook();
+    }
+
+    let contents = serde_json::to_vec(&*STATE.lock().unwrap())?;
+
+    let tmp_path = config.path.with_extension("tmp");
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, &config.path)?;
+
+    Ok(())
+}
+
+/// Periodically saves the snapshot. The final save on shutdown is left to the caller,
+/// once everything that owns state has stopped.
+pub async fn run(config: &'static StateConfig, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(config.interval);
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = save() {
+                    error!(%e, path = ?config.path, "Failed to save state");
+                }
+            }
+            _ = shutdown.changed() => break,
+        }
+    }
+}
@@ -0,0 +1,36 @@
+/// Renders `{{name}}` placeholders in a template.
+///
+/// Each placeholder is looked up by its trimmed name and passed through `escape`.
+/// Unknown placeholders render as an empty string.
+pub fn render<L, E>(template: &str, lookup: L, escape: E) -> String
+where
+    L: Fn(&str) -> Option<String>,
+    E: Fn(&str) -> String,
+{
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+
+        output.push_str(&rest[..start]);
+
+        let name = rest[start + 2..start + 2 + end].trim();
+        if let Some(value) = lookup(name) {
+            output.push_str(&escape(&value));
+        }
+
+        rest = &rest[start + 2 + end + 2..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Escapes a value for interpolation inside a JSON string literal
+pub fn escape_json(value: &str) -> String {
+    let quoted = serde_json::Value::from(value).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}